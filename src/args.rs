@@ -1,3 +1,4 @@
+use crate::merge::MergeStrategy;
 use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
 use clap_complete::Shell;
 use console::style;
@@ -13,6 +14,10 @@ pub struct Arguments {
     #[arg(short, long, value_name = "file")]
     pub config: Option<PathBuf>,
 
+    /// Print diagnostic output.
+    #[arg(long, global = true)]
+    pub verbose: bool,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 
@@ -40,6 +45,14 @@ pub enum Commands {
     #[command(subcommand)]
     Var(VarSubcommand),
 
+    /// Machine profiles (machines/<name>.yml in sources)
+    #[command(subcommand)]
+    Machine(MachineSubcommand),
+
+    /// Evaluate an expression (ie. to test `when:` conditions)
+    #[command(after_long_help = EVAL_HELP.as_str())]
+    Eval(EvalArgs),
+
     /// Generate shell completions
     #[command(after_long_help = COMPLETION_EXAMPLES_HELP.as_str())]
     Completion(CompletionArgs),
@@ -65,9 +78,32 @@ pub enum PluginSubcommand {
 
 #[derive(Debug, Subcommand)]
 pub enum VarSubcommand {
+    /// Print a global variable
+    #[command(after_long_help = VAR_GET_HELP.as_str())]
+    Get(VarGetArgs),
+
     /// Set a global variable
     #[command(after_long_help = VAR_SET_HELP.as_str())]
     Set(VarSetArgs),
+
+    /// Unset a global variable
+    Unset(VarUnsetArgs),
+
+    /// List variables (and where they're set)
+    List,
+
+    /// Edit global variables (~/.nk/globals.yml) in $EDITOR
+    Edit,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MachineSubcommand {
+    /// List machine profiles (and which one is selected)
+    List,
+
+    /// Print a machine profile
+    #[command(after_long_help = MACHINE_SHOW_HELP.as_str())]
+    Show(MachineShowArgs),
 }
 
 pub struct CompletionFile {
@@ -127,16 +163,37 @@ lazy_static! {
         [
             "  - The file names for assets are generated based on the `when:` conditions in your plugin.yml",
             "  - Any simple (`var == \"value\"`) conditions for system vars will contribute to the file name",
-            "    - System vars include: distro, os, family, and arch",
+            "    - System vars include: distro, os, family, and arch (and distro_version alongside distro)",
             "  - ie. `when: [os == \"macos\", arch == \"aarch64\"]`, will produce `{plugin}-macos-aarch64.tar.gz`",
             "  - For now, anything more complicated will need to be packed manually"
         ].join("\n")
     );
-    static ref VAR_SET_HELP: String = format!(
+    static ref EVAL_HELP: String = format!(
+        "{}\n{}\n{}\n{}",
+        style("Examples:").underlined().bold(),
+        "  $ nk eval 'os == \"macos\" && command_exists(\"brew\")'",
+        "  $ nk eval 'roles'",
+        "  $ nk eval --declaration packages --state ffmpeg 'declaration == \"packages\"'"
+    );
+    static ref VAR_GET_HELP: String = format!(
         "{}\n{}\n{}",
         style("Examples:").underlined().bold(),
+        "  $ nk var get machine",
+        "  $ nk var get --output json roles"
+    );
+    static ref VAR_SET_HELP: String = format!(
+        "{}\n{}\n{}\n{}\n{}",
+        style("Examples:").underlined().bold(),
         "  $ nk var set machine 'some-machine'",
-        "  $ nk var set roles '[some, roles]'"
+        "  $ nk var set roles '[some, roles]'",
+        "  $ nk var set roles --append 'work'",
+        "  $ nk var set git.email 'me@example.com'"
+    );
+    static ref MACHINE_SHOW_HELP: String = format!(
+        "{}\n{}\n{}",
+        style("Examples:").underlined().bold(),
+        "  $ nk machine show",
+        "  $ nk machine show work-laptop"
     );
 }
 
@@ -157,14 +214,67 @@ pub struct ProvisionArgs {
     #[arg(short, long, value_name = "format", default_value_t = ProvisionOutputFormat::Pretty)]
     pub output: ProvisionOutputFormat,
 
+    /// Only provision states for the given plugin(s)
+    #[arg(long = "plugin", value_name = "name")]
+    pub plugins: Vec<String>,
+
+    /// Only provision states for the given declaration(s)
+    #[arg(long = "declaration", value_name = "name")]
+    pub declarations: Vec<String>,
+
+    /// Skip states matching the given filter(s)
+    #[arg(long = "exclude", value_name = "filter")]
+    pub excludes: Vec<String>,
+
     /// filter the states
     /// examples:
     /// $ nk p 'declaration == "packages"'
     /// $ nk p 'state == "ffmpeg"'
+    /// $ nk p 'plugin == "homebrew"'
+    /// $ nk p 'plugin.version == "v0.12.0"'
     #[clap(verbatim_doc_comment)]
     pub filter: Option<String>,
 }
 
+impl ProvisionArgs {
+    // NOTE: combines the filter with the convenience flags into a single expression
+    pub fn filter_expression(&self) -> Option<String> {
+        let quote = |name: &String| {
+            format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+        };
+        let any_of = |var: &str, names: &[String]| {
+            format!(
+                "({})",
+                names
+                    .iter()
+                    .map(|n| format!("{var} == {}", quote(n)))
+                    .collect::<Vec<_>>()
+                    .join(" || ")
+            )
+        };
+
+        let mut parts = vec![];
+        if !self.plugins.is_empty() {
+            parts.push(any_of("plugin", &self.plugins));
+        }
+        if !self.declarations.is_empty() {
+            parts.push(any_of("declaration", &self.declarations));
+        }
+        if let Some(filter) = &self.filter {
+            parts.push(format!("({filter})"));
+        }
+        for exclude in &self.excludes {
+            parts.push(format!("!({exclude})"));
+        }
+
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(" && "))
+        }
+    }
+}
+
 #[derive(Debug, Clone, ValueEnum, Display)]
 #[strum(serialize_all = "snake_case")]
 pub enum ResolveOutputFormat {
@@ -219,15 +329,99 @@ pub struct PackArgs {
     pub paths: Vec<PathBuf>,
 }
 
+#[derive(Debug, Clone, ValueEnum, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum VarOutputFormat {
+    Yaml,
+    Json,
+}
+
 #[derive(Debug, Args)]
-pub struct VarSetArgs {
+pub struct VarGetArgs {
+    #[arg(short, long, value_name = "format", default_value_t = VarOutputFormat::Yaml)]
+    pub output: VarOutputFormat,
+
     /// Variable name
     #[arg(value_name = "name")]
     pub name: String,
+}
+
+#[derive(Debug, Args)]
+pub struct VarSetArgs {
+    /// Variable name (use dots for nested keys, ie. git.email)
+    #[arg(value_name = "name")]
+    pub name: String,
 
     /// Variable value (as yaml)
     #[arg(value_name = "value")]
     pub value: String,
+
+    /// Append to the existing list
+    #[arg(long, group = "strategy")]
+    pub append: bool,
+
+    /// Prepend to the existing list
+    #[arg(long, group = "strategy")]
+    pub prepend: bool,
+
+    /// Append values not already in the existing list
+    #[arg(long, group = "strategy")]
+    pub unique_append: bool,
+
+    /// Remove values from the existing list
+    #[arg(long, group = "strategy")]
+    pub remove: bool,
+
+    /// Replace the existing value (instead of merging mappings)
+    #[arg(long, group = "strategy")]
+    pub replace: bool,
+}
+
+impl VarSetArgs {
+    pub const fn merge_strategy(&self) -> Option<MergeStrategy> {
+        if self.append {
+            Some(MergeStrategy::Append)
+        } else if self.prepend {
+            Some(MergeStrategy::Prepend)
+        } else if self.unique_append {
+            Some(MergeStrategy::UniqueAppend)
+        } else if self.remove {
+            Some(MergeStrategy::Remove)
+        } else if self.replace {
+            Some(MergeStrategy::Replace)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct VarUnsetArgs {
+    /// Variable name
+    #[arg(value_name = "name")]
+    pub name: String,
+}
+
+#[derive(Debug, Args)]
+pub struct MachineShowArgs {
+    /// Machine profile name (defaults to the selected profile)
+    #[arg(value_name = "name")]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct EvalArgs {
+    /// Expression to evaluate
+    #[arg(value_name = "expression")]
+    pub expression: String,
+
+    /// Declaration name to make available as `declaration`
+    #[arg(long, value_name = "name")]
+    pub declaration: Option<String>,
+
+    /// State (as yaml) to make available as `state`
+    #[arg(long, value_name = "state")]
+    pub state: Option<String>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -0,0 +1,58 @@
+use crate::{
+    args::EvalArgs, config::Config, eval::Evaluator,
+    resolve::get_vars_with_plugins, vars::get_global_vars,
+};
+use console::style;
+use rhai::{
+    serde::{from_dynamic, to_dynamic},
+    Scope,
+};
+use serde_yml::Value;
+use std::collections::HashMap;
+
+pub async fn eval(
+    args: EvalArgs,
+    config: Result<Config, Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // initialize global vars (including the machine profile and vars plugins, if there's a config)
+    let global_vars = match config {
+        Ok(config) => {
+            let global_vars = get_global_vars(&config.sources)?;
+            get_vars_with_plugins(&config, global_vars, &mut HashMap::new())
+                .await?
+        }
+        Err(e) => {
+            eprintln!(
+                "{}",
+                style(format!(
+                    "machine profile and plugin vars not included: {e}"
+                ))
+                .yellow()
+            );
+            get_global_vars(&[])?
+        }
+    };
+
+    // initialize evaluator
+    let evaluator = Evaluator::new(global_vars);
+
+    // populate scope (same as when matching states to plugins)
+    let mut scope = Scope::new();
+    if let Some(declaration) = args.declaration {
+        scope.push_constant("declaration", declaration);
+    }
+    if let Some(state) = args.state {
+        let state: Value = serde_yml::from_str(&state)
+            .map_err(|e| format!("{e}: while parsing state: {state}"))?;
+        scope.push_constant_dynamic("state", to_dynamic(state)?);
+    }
+
+    // evaluate
+    let result = evaluator.eval_expression(&args.expression, &mut scope)?;
+
+    // print result
+    let value: Value = from_dynamic(&result)?;
+    print!("{}", serde_yml::to_string(&value)?);
+
+    Ok(())
+}
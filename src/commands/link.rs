@@ -1,16 +1,16 @@
 use itertools::Itertools;
 
-use crate::{args::LinkArgs, plugins::PluginFile};
-use std::{
-    fs,
-    path::{Path, PathBuf},
-    str::FromStr,
+use crate::{
+    args::LinkArgs,
+    lock::{nk_dir, NkLock},
+    plugins::PluginFile,
 };
+use std::{fs, path::Path};
 
 pub fn link(args: &LinkArgs) -> Result<(), Box<dyn std::error::Error>> {
     // create nk plugin directory
-    let nk_plugins_dir =
-        PathBuf::from_str(&shellexpand::tilde("~/.nk/plugins"))?;
+    let _lock = NkLock::acquire()?;
+    let nk_plugins_dir = nk_dir()?.join("plugins");
     fs::create_dir_all(&nk_plugins_dir)?;
 
     for path in &args.paths {
@@ -0,0 +1,69 @@
+use console::style;
+
+use crate::{
+    args::MachineShowArgs,
+    config::Config,
+    vars::{get_global_vars, select_machine_profile, MachineProfile},
+};
+
+pub fn machine_list(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let profiles = MachineProfile::find_all(&config.sources)?;
+    let vars = get_global_vars(&config.sources)?;
+    let selected = select_machine_profile(&profiles, &vars)?;
+
+    for profile in &profiles {
+        let is_selected = selected.is_some_and(|p| p.name == profile.name);
+        let name =
+            format!("{} {}", if is_selected { "*" } else { " " }, profile.name);
+
+        let mut details = vec![];
+        if !profile.hostnames.is_empty() {
+            details
+                .push(format!("hostnames: {}", profile.hostnames.join(", ")));
+        }
+        if !profile.roles.is_empty() {
+            details.push(format!("roles: {}", profile.roles.join(", ")));
+        }
+
+        println!(
+            "{} {}",
+            if is_selected {
+                style(name).bold().green()
+            } else {
+                style(name).bold()
+            },
+            style(format!("# {}", details.join("; "))).dim()
+        );
+    }
+
+    Ok(())
+}
+
+pub fn machine_show(
+    args: MachineShowArgs,
+    config: Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let profiles = MachineProfile::find_all(&config.sources)?;
+
+    let profile = if let Some(name) = &args.name {
+        profiles
+            .iter()
+            .find(|p| &p.name == name)
+            .ok_or_else(|| format!("machine profile not found: {name}"))?
+    } else {
+        let vars = get_global_vars(&config.sources)?;
+        select_machine_profile(&profiles, &vars)?.ok_or_else(|| {
+            format!(
+                "no machine profile selected (hostname: {})",
+                vars.get("hostname")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+            )
+        })?
+    };
+
+    println!("{}", style(format!("# {}", profile.path.display())).dim());
+    print!("{}", serde_yml::to_string(profile)?);
+
+    Ok(())
+}
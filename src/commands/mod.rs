@@ -1,14 +1,18 @@
 mod completion;
+mod eval;
 mod helper;
 mod link;
+mod machine;
 mod pack;
 mod provision;
 mod resolve;
 mod var;
 
 pub use self::completion::*;
+pub use self::eval::*;
 pub use self::helper::*;
 pub use self::link::*;
+pub use self::machine::*;
 pub use self::pack::*;
 pub use self::provision::*;
 pub use self::resolve::*;
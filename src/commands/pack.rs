@@ -120,6 +120,7 @@ fn get_asset_filename(name: &str, when: &[Condition]) -> String {
     // ie.
     // - `os == "macos"` -> {name}-macos
     // - [`os == "macos"`, `arch == "aarch64"`] -> {name}-macos-aarch64
+    // - [`distro == "ubuntu"`, `distro_version == "24.04"`] -> {name}-ubuntu-24.04
     // - `family == "unix"` -> {name}-unix
 
     let conditions = when.iter().map(|c| &c.rule).collect::<Vec<_>>();
@@ -132,6 +133,13 @@ fn get_asset_filename(name: &str, when: &[Condition]) -> String {
     {
         // distro
         filename_parts.push(distro.to_string());
+
+        // distro version (only alongside a distro)
+        if let Some(version) = conditions.iter().find_map(|c| {
+            c.strip_prefix("distro_version == \"")?.strip_suffix('"')
+        }) {
+            filename_parts.push(version.to_string());
+        }
     } else if let Some((os, _)) = SystemOs::iter()
         .map(|os| (os, format!("os == \"{}\"", os)))
         .find(|(_, condition)| conditions.contains(&condition))
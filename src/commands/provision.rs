@@ -4,13 +4,16 @@ use crate::{
     args::{ProvisionArgs, ProvisionOutputFormat},
     config::Config,
     eval::{DeclaredState, Evaluator},
+    merge::merge_states_by_key,
     plugins::{
         load_plugins, Plugin, ProvisionInfo, ProvisionStateOutput,
         ProvisionStateStatus,
     },
     resolve::{resolve, ResolveOptions},
     root::{ensure_not_root, sudo_prompt},
+    secrets::{contains_secret, redact, redact_value},
     sort::sort_execution_sets,
+    state::Condition,
     vars::get_global_vars,
 };
 use console::style;
@@ -113,12 +116,19 @@ pub async fn provision(
     // ensure not running as root
     ensure_not_root()?;
 
+    // parse filter (so syntax errors are reported before doing any work)
+    let filter = args
+        .filter_expression()
+        .map(Condition::parse)
+        .transpose()
+        .map_err(|e| format!("{e}: in filter"))?;
+
     // run sudo once (so the user can be prompted for their password if required, and any additional sudo's will hopefully be within the password timeout period)
     // TODO: if this is insufficient, may want to consider running sudo periodically during the provision (to refresh the timeout)
     sudo_prompt()?;
 
     // initialize global vars
-    let global_vars = get_global_vars()?;
+    let global_vars = get_global_vars(&config.sources)?;
 
     // initialize evaluator
     let evaluator = Evaluator::new(global_vars.clone());
@@ -139,9 +149,12 @@ pub async fn provision(
     let mut execution_sets =
         evaluator.match_states_to_plugins(&resolved.declarations, &plugins)?;
 
+    // merge states with the same identity key
+    merge_states_by_key(&mut execution_sets)?;
+
     // filter
-    if let Some(filter) = &args.filter {
-        evaluator.filter_execution_sets(&mut execution_sets, filter);
+    if let Some(filter) = &filter {
+        evaluator.filter_execution_sets(&mut execution_sets, filter)?;
     }
 
     // sort execution sets
@@ -149,6 +162,7 @@ pub async fn provision(
 
     // validate
     validate(&execution_sets)?;
+    validate_secrets(&execution_sets)?;
 
     let formatter: Box<dyn Formatter> = match args.output {
         ProvisionOutputFormat::Pretty => Box::new(PrettyFormatter {}),
@@ -157,18 +171,31 @@ pub async fn provision(
 
     // provision
     let provision_info = ProvisionInfo {
+        sources: config.sources.clone(),
+        vars: resolved.vars.clone(),
+    };
+    let redacted_provision_info = ProvisionInfo {
         sources: config.sources,
-        vars: resolved.vars,
+        vars: redact_value(&resolved.vars.into())
+            .as_mapping()
+            .cloned()
+            .unwrap_or_default(),
     };
     let mut lock = stdout().lock();
     let writer = lock.by_ref();
     let provision_results = execution_sets
         .iter()
         .map(|(p, v)| {
-            match p.provision(&provision_info, v) {
+            let info = if p.definition.secrets {
+                &provision_info
+            } else {
+                &redacted_provision_info
+            };
+            match p.provision(info, v) {
                 Ok(i) => Ok(i
                     .flatten()
                     .map(|line| {
+                        let line = redact(&line);
                         let result =
                             serde_json::from_str::<ProvisionStateOutput>(&line);
                         formatter
@@ -183,7 +210,8 @@ pub async fn provision(
                     writeln!(
                         writer,
                         "plugin failed provisioning {}: {}",
-                        p.definition.name, e
+                        p.definition.name,
+                        redact(&e.to_string())
                     )?;
                     Err(e)
                 }
@@ -198,7 +226,7 @@ pub async fn provision(
     if provision_results.is_err()
         || provision_results.unwrap().iter().any(Result::is_err)
     {
-        return Err("provisioning failed...")?;
+        Err("provisioning failed...")?;
     }
 
     Ok(())
@@ -223,7 +251,7 @@ fn validate(
                                     .map_err(|e| {
                                         format!("{e}: {:?}", state.state)
                                     }) {
-                                    Ok(v) | Err(v) => v,
+                                    Ok(v) | Err(v) => redact(&v),
                                 };
                             println!(
                                 "{}",
@@ -253,3 +281,26 @@ fn validate(
         Ok(())
     }
 }
+
+// NOTE: secrets are only passed to plugins that declare they handle them
+fn validate_secrets(
+    execution_sets: &[(Plugin, Vec<DeclaredState>)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for (plugin, states) in execution_sets {
+        if plugin.definition.secrets {
+            continue;
+        }
+
+        for state in states {
+            if contains_secret(&serde_json::to_string(&state.state)?) {
+                return Err(format!(
+                    "{}: contains a secret, but plugin: {} doesn't accept secrets (secrets: true)",
+                    state.declaration, plugin.definition.name
+                )
+                .into());
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -5,6 +5,7 @@ use crate::{
     plugins::load_plugins,
     resolve::resolve as resolveState,
     resolve::ResolveOptions,
+    secrets::redact,
     vars::get_global_vars,
 };
 
@@ -14,7 +15,7 @@ pub async fn resolve(
     config: Config,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // initialize global vars
-    let global_vars = get_global_vars()?;
+    let global_vars = get_global_vars(&config.sources)?;
 
     // initialize evaluator
     let evaluator = Evaluator::new(global_vars.clone());
@@ -36,10 +37,10 @@ pub async fn resolve(
     // print state
     match args.output {
         ResolveOutputFormat::Yaml => {
-            print!("{}", serde_yml::to_string(&resolved)?);
+            print!("{}", redact(&serde_yml::to_string(&resolved)?));
         }
         ResolveOutputFormat::Json => {
-            println!("{}", serde_json::to_string(&resolved)?);
+            println!("{}", redact(&serde_json::to_string(&resolved)?));
         }
     };
 
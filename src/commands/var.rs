@@ -1,19 +1,503 @@
-use serde_yml::Value;
+use console::style;
+use serde_yml::{
+    value::{Tag, TaggedValue},
+    Mapping, Value,
+};
+use std::{
+    collections::HashMap,
+    env,
+    io::{stdin, Write},
+    path::Path,
+    process::Command,
+};
 
-use crate::{args::VarSetArgs, vars::Globals};
+use crate::{
+    args::{VarGetArgs, VarOutputFormat, VarSetArgs, VarUnsetArgs},
+    config::Config,
+    eval::Evaluator,
+    lock::{nk_dir, NkLock},
+    merge::merge_vars,
+    render::render_group,
+    resolve::get_vars_with_plugins,
+    secrets::{redact, Secrets},
+    state::{self, ResolvedGroup},
+    vars::{
+        get_builtin_vars, get_env_vars, get_global_vars, get_machine_vars,
+        select_machine_profile, Globals, MachineProfile,
+    },
+};
 
-pub fn var_set(args: VarSetArgs) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn var_get(
+    args: VarGetArgs,
+    config: Result<Config, Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // NOTE: machine profiles and vars plugins are only included when run from a directory with a config
+    let vars = match config {
+        Ok(config) => {
+            let global_vars = get_global_vars(&config.sources)?;
+            get_vars_with_plugins(&config, global_vars, &mut HashMap::new())
+                .await?
+        }
+        Err(e) => {
+            eprintln!(
+                "{}",
+                style(format!(
+                    "machine profile and plugin vars not included: {e}"
+                ))
+                .yellow()
+            );
+            get_global_vars(&[])?
+        }
+    };
+
+    let path = parse_var_path(&args.name)?;
+    let value = get_var_path(&vars, &path)
+        .ok_or_else(|| format!("var not found: {}", args.name))?;
+
+    match args.output {
+        VarOutputFormat::Yaml => print!("{}", serde_yml::to_string(value)?),
+        VarOutputFormat::Json => println!("{}", serde_json::to_string(value)?),
+    };
+
+    Ok(())
+}
+
+pub fn var_set(
+    args: VarSetArgs,
+    config: Result<Config, Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
     // parse value
-    let value: Value = serde_yml::from_str(&args.value)?;
+    let mut value: Value = serde_yml::from_str(&args.value)?;
+    if let Some(strategy) = args.merge_strategy() {
+        value = Value::Tagged(Box::new(TaggedValue {
+            tag: Tag::new(strategy.tag()),
+            value,
+        }));
+    }
+    let path = parse_var_path(&args.name)?;
 
     // load existing globals
+    let lock = NkLock::acquire()?;
     let mut globals = Globals::load()?;
 
+    // NOTE: machine profiles are only included when run from a directory with a config
+    let sources = config.map(|c| c.sources).unwrap_or_default();
+    let machine_vars = get_machine_vars(&sources, &globals.overrides()?)?;
+
     // set new value
-    globals.vars.insert(args.name.into(), value);
+    set_var_path(&mut globals.vars, &machine_vars, &path, value);
+
+    // save globals
+    globals.save(&lock)?;
+
+    Ok(())
+}
+
+// NOTE: builtin/machine profile vars are merged into as well (ie. appending to
+// roles), so the var is seeded with its current value if not set in globals
+fn set_var_path(
+    vars: &mut Mapping,
+    machine_vars: &Mapping,
+    path: &[&str],
+    value: Value,
+) {
+    if !vars.contains_key(path[0]) {
+        if let Some(current) = machine_vars.get(path[0]) {
+            vars.insert(path[0].into(), current.clone());
+        }
+    }
+
+    // nest value under its path (ie. git.email => {git: {email: value}})
+    let nested = path.iter().rev().fold(value, |value, key| {
+        Value::Mapping(Mapping::from_iter([((*key).into(), value)]))
+    });
+    let Value::Mapping(nested) = nested else {
+        unreachable!("var path should not be empty");
+    };
+
+    merge_vars(vars, nested);
+}
+
+pub fn var_unset(args: VarUnsetArgs) -> Result<(), Box<dyn std::error::Error>> {
+    // load existing globals
+    let lock = NkLock::acquire()?;
+    let mut globals = Globals::load()?;
+
+    // remove value (nested under its path, ie. git.email)
+    let path = parse_var_path(&args.name)?;
+    if !remove_var_path(&mut globals.vars, &path) {
+        return Err(format!(
+            "var not set in {}: {}",
+            Globals::path()?.display(),
+            args.name
+        )
+        .into());
+    }
 
     // save globals
-    globals.save()?;
+    globals.save(&lock)?;
+
+    Ok(())
+}
+
+// NOTE: dot separated path to a (nested) var, ie. git.email
+fn parse_var_path(name: &str) -> Result<Vec<&str>, Box<dyn std::error::Error>> {
+    let path = name.split('.').collect::<Vec<_>>();
+    if path.iter().any(|k| k.is_empty()) {
+        return Err(format!("invalid var name: {name}").into());
+    }
+
+    Ok(path)
+}
+
+fn get_var_path<'a>(vars: &'a Mapping, path: &[&str]) -> Option<&'a Value> {
+    match path {
+        [] => None,
+        [key] => vars.get(*key),
+        [key, rest @ ..] => match vars.get(*key) {
+            Some(Value::Mapping(nested)) => get_var_path(nested, rest),
+            _ => None,
+        },
+    }
+}
+
+// NOTE: mappings left empty by the removal are removed as well
+fn remove_var_path(vars: &mut Mapping, path: &[&str]) -> bool {
+    match path {
+        [] => false,
+        [key] => vars.remove(*key).is_some(),
+        [key, rest @ ..] => {
+            let Some(Value::Mapping(nested)) = vars.get_mut(*key) else {
+                return false;
+            };
+            let removed = remove_var_path(nested, rest);
+            if removed && nested.is_empty() {
+                vars.remove(*key);
+            }
+            removed
+        }
+    }
+}
+
+pub async fn var_list(
+    config: Result<Config, Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let builtin_vars = get_builtin_vars()?.to_mapping();
+    let globals = Globals::load()?;
+    let env_vars = get_env_vars();
+    let sources = config
+        .as_ref()
+        .map(|c| c.sources.clone())
+        .unwrap_or_default();
+    let global_vars = get_global_vars(&sources)?;
+    let machine_vars = select_machine_profile(
+        &MachineProfile::find_all(&sources)?,
+        &global_vars,
+    )?
+    .map(MachineProfile::to_vars)
+    .unwrap_or_default();
+
+    // NOTE: plugin/state vars are only included when run from a directory with a config
+    let mut plugin_origins = HashMap::new();
+    let (vars, groups) = match config {
+        Ok(config) => {
+            let global_vars = get_vars_with_plugins(
+                &config,
+                global_vars,
+                &mut plugin_origins,
+            )
+            .await?;
+            let evaluator = Evaluator::new(global_vars.clone());
+            let files = state::File::find_all(&config.sources, config.verbose)?;
+            let groups = evaluator.filter_files_to_matching_groups(&files)?;
+
+            let mut group = ResolvedGroup::new(global_vars);
+            for g in &groups {
+                merge_vars(&mut group.vars, g.vars.clone());
+                group.record_expression_paths(g);
+            }
+            let secrets = Secrets::new(config.secrets);
+            let resolved = render_group(group, &evaluator, &secrets)?;
+
+            (resolved.vars, groups)
+        }
+        Err(e) => {
+            eprintln!(
+                "{}",
+                style(format!("plugin and state vars not included: {e}"))
+                    .yellow()
+            );
+            (global_vars, vec![])
+        }
+    };
+
+    for (name, value) in &vars {
+        let mut origins = vec![];
+        if builtin_vars.contains_key(name) {
+            origins.push("builtin");
+        }
+        if machine_vars.contains_key(name) {
+            origins.push("machine profile");
+        }
+        if globals.vars.contains_key(name)
+            || name
+                .as_str()
+                .is_some_and(|n| globals.sources.contains_key(n))
+        {
+            origins.push("globals.yml");
+        }
+        if env_vars.contains_key(name) {
+            origins.push("env");
+        }
+        if name
+            .as_str()
+            .is_some_and(|n| plugin_origins.contains_key(n))
+        {
+            origins.push("vars plugin");
+        }
+        if groups.iter().any(|g| g.vars.contains_key(name)) {
+            origins.push("state");
+        }
+
+        let origin = match origins.split_last() {
+            Some((origin, [])) => (*origin).to_string(),
+            Some((origin, overridden)) => {
+                format!("{origin}, overrides {}", overridden.join(", "))
+            }
+            None => String::new(),
+        };
+
+        println!(
+            "{}: {} {}",
+            style(name.as_str().unwrap_or_default()).bold(),
+            redact(&serde_json::to_string(value)?),
+            style(format!("# {origin}")).dim()
+        );
+    }
+
+    Ok(())
+}
+
+pub fn var_edit() -> Result<(), Box<dyn std::error::Error>> {
+    let path = Globals::path()?;
+    let original = read_globals(&path)?;
+
+    // NOTE: edits a copy, so globals.yml is only changed once it's valid
+    nk_dir()?;
+    let edit_path = path.with_file_name("globals.edit.yml");
+    std::fs::write(&edit_path, &original)
+        .map_err(|e| format!("{e}: {}", edit_path.display()))?;
+
+    let Some(contents) = edit_until_valid(&edit_path)? else {
+        std::fs::remove_file(&edit_path)?;
+        return Err(format!("{} was not changed", path.display()).into());
+    };
+
+    if contents != original {
+        // NOTE: the lock isn't held while editing (so other commands aren't
+        // blocked), so make sure nothing changed in the meantime
+        let lock = NkLock::acquire()?;
+        if read_globals(&path)? != original {
+            return Err(format!(
+                "{} was changed while editing, edits were left in: {}",
+                path.display(),
+                edit_path.display()
+            )
+            .into());
+        }
+
+        Globals::write(&contents, &lock)?;
+    }
+    std::fs::remove_file(&edit_path)?;
+
+    Ok(())
+}
+
+// NOTE: missing globals are edited as the default (empty) globals
+fn read_globals(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(contents),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Ok(serde_yml::to_string(&Globals::default())?)
+        }
+        Err(e) => Err(format!("{e}: {}", path.display()).into()),
+    }
+}
+
+// NOTE: returns None if the user gives up on fixing invalid globals
+fn edit_until_valid(
+    path: &Path,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    loop {
+        run_editor(path)?;
+
+        let contents = std::fs::read_to_string(path)?;
+        match Globals::parse(&contents) {
+            Ok(_) => return Ok(Some(contents)),
+            Err(e) => {
+                print!(
+                    "{}\nedit again? [Y/n] ",
+                    style(format!("invalid globals: {e}")).red()
+                );
+                std::io::stdout().flush()?;
+
+                let mut answer = String::new();
+                let read = stdin().read_line(&mut answer)?;
+                if read == 0 || answer.trim().eq_ignore_ascii_case("n") {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+fn run_editor(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    // NOTE: editors may include arguments (ie. `code --wait`)
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".into());
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().ok_or("$EDITOR should not be empty")?;
+
+    let status = Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .map_err(|e| format!("{e}: {editor}"))?;
+    if !status.success() {
+        return Err(format!("editor exited with: {status}").into());
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml<T: serde::de::DeserializeOwned>(contents: &str) -> T {
+        serde_yml::from_str(contents).unwrap()
+    }
+
+    #[test]
+    fn parse_nested_var_path() {
+        assert_eq!(parse_var_path("git.email").unwrap(), ["git", "email"]);
+        assert_eq!(parse_var_path("roles").unwrap(), ["roles"]);
+        assert!(parse_var_path("git.").is_err());
+        assert!(parse_var_path(".git").is_err());
+    }
+
+    #[test]
+    fn get_nested_var() {
+        let vars = yaml("{git: {email: me@example.com}, roles: [a]}");
+
+        assert_eq!(
+            get_var_path(&vars, &["git", "email"]),
+            Some(&"me@example.com".into())
+        );
+        assert_eq!(
+            get_var_path(&vars, &["git"]),
+            Some(&yaml("email: me@example.com"))
+        );
+        assert_eq!(get_var_path(&vars, &["git", "name"]), None);
+        assert_eq!(get_var_path(&vars, &["roles", "a"]), None);
+    }
+
+    #[test]
+    fn remove_nested_var() {
+        let mut vars = yaml("git: {email: me@example.com, name: me}");
+
+        assert!(remove_var_path(&mut vars, &["git", "email"]));
+        assert_eq!(vars, yaml("git: {name: me}"));
+    }
+
+    #[test]
+    fn remove_nested_var_prunes_empty_mappings() {
+        let mut vars = yaml("{git: {email: me@example.com}, roles: []}");
+
+        assert!(remove_var_path(&mut vars, &["git", "email"]));
+        assert_eq!(vars, yaml("roles: []"));
+    }
+
+    #[test]
+    fn remove_missing_var() {
+        let mut vars = yaml("{git: {name: me}, roles: [a]}");
+
+        assert!(!remove_var_path(&mut vars, &["git", "email"]));
+        assert!(!remove_var_path(&mut vars, &["roles", "a"]));
+        assert!(!remove_var_path(&mut vars, &["editor"]));
+        assert_eq!(vars, yaml("{git: {name: me}, roles: [a]}"));
+    }
+
+    fn machine_vars() -> Mapping {
+        let profile = MachineProfile {
+            name: "laptop".into(),
+            path: "machines/laptop.yml".into(),
+            hostnames: vec![],
+            roles: vec!["work".into(), "dev".into()],
+            vars: yaml("git: {name: me, email: me@work.example.com}"),
+        };
+        let mut vars = yaml("{hostname: laptop, roles: []}");
+        merge_vars(&mut vars, profile.to_vars());
+        vars
+    }
+
+    #[test]
+    fn set_var_appends_to_machine_profile() {
+        let machine_vars = machine_vars();
+        let mut globals = Mapping::new();
+
+        set_var_path(
+            &mut globals,
+            &machine_vars,
+            &["roles"],
+            serde_yml::from_str("!append gaming").unwrap(),
+        );
+
+        let mut vars = machine_vars;
+        merge_vars(&mut vars, globals);
+        assert_eq!(
+            vars.get("roles"),
+            Some(&yaml::<Value>("[work, dev, gaming]"))
+        );
+    }
+
+    #[test]
+    fn set_nested_var_keeps_machine_profile_siblings() {
+        let machine_vars = machine_vars();
+        let mut globals = Mapping::new();
+
+        set_var_path(
+            &mut globals,
+            &machine_vars,
+            &["git", "email"],
+            "me@example.com".into(),
+        );
+
+        let mut vars = machine_vars;
+        merge_vars(&mut vars, globals);
+        assert_eq!(
+            vars.get("git"),
+            Some(&yaml::<Value>("{name: me, email: me@example.com}"))
+        );
+    }
+
+    #[test]
+    fn globals_merge_over_machine_profile() {
+        let mut vars = machine_vars();
+
+        merge_vars(
+            &mut vars,
+            yaml("{roles: !append [gaming], git: {email: me@example.com}}"),
+        );
+
+        assert_eq!(
+            vars.get("roles"),
+            Some(&yaml::<Value>("[work, dev, gaming]"))
+        );
+        assert_eq!(
+            vars.get("git"),
+            Some(&yaml::<Value>("{name: me, email: me@example.com}"))
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::args::Arguments;
+use crate::{args::Arguments, secrets::SecretsConfig};
 use lazy_static::lazy_static;
 use path_clean::PathClean;
 use regex::Regex;
@@ -14,6 +14,13 @@ pub struct Config {
     #[serde(deserialize_with = "expand_paths")]
     pub sources: Vec<PathBuf>,
     pub plugins: Vec<ConfigPlugin>,
+    #[serde(default)]
+    pub secrets: Option<SecretsConfig>,
+    // NOTE: json schema for the merged vars (validated after rendering)
+    #[serde(default)]
+    pub vars_schema: Option<serde_yml::Value>,
+    #[serde(skip)]
+    pub verbose: bool,
 }
 
 impl Config {
@@ -35,6 +42,7 @@ impl Config {
         }?;
 
         let mut conf: Self = serde_yml::from_str(&contents)?;
+        conf.verbose = arguments.verbose;
 
         // TODO: maybe there should we a way to flag sources as optional?
         conf.sources.retain(|s| s.exists());
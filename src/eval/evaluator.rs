@@ -0,0 +1,367 @@
+use super::{register_functions, register_plugin_info, PluginInfo};
+use crate::{
+    merge::merge_vars,
+    plugins::{Plugin, PluginDefinitionPartial},
+    secrets::redact,
+    state::{self, Condition},
+};
+use console::style;
+use itertools::Itertools;
+use rhai::{serde::to_dynamic, Dynamic, Engine, Scope};
+use serde::{Deserialize, Serialize};
+use serde_yml::{Mapping, Value};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+pub struct Evaluator {
+    engine: Engine,
+    // NOTE: shared with the engine's variable resolver & functions
+    vars: Arc<RwLock<Mapping>>,
+}
+
+impl Evaluator {
+    pub fn new(global_vars: Mapping) -> Self {
+        let vars = Arc::new(RwLock::new(global_vars));
+
+        // setup engine
+        let mut engine = Engine::new();
+        register_functions(&mut engine, &vars);
+        register_plugin_info(&mut engine);
+        let resolver_vars = vars.clone();
+        #[allow(deprecated)]
+        engine.on_var(move |name, _index, context| {
+            // NOTE: the resolver takes precedence over the scope, so local
+            // variables (ie. closure params) must be deferred to explicitly
+            if context.scope().contains(name) {
+                return Ok(None);
+            }
+
+            let vars = resolver_vars.read().expect("evaluator vars lock");
+            Ok(match vars.get(name).unwrap_or(&Value::Null) {
+                Value::Null => None,
+                Value::Bool(v) => Some(to_dynamic(v)?),
+                Value::Number(v) => Some(to_dynamic(v)?),
+                Value::String(v) => Some(to_dynamic(v)?),
+                Value::Sequence(v) => Some(to_dynamic(v)?),
+                Value::Mapping(v) => Some(to_dynamic(v)?),
+                Value::Tagged(v) => Some(to_dynamic(v)?),
+            })
+        });
+
+        Evaluator { engine, vars }
+    }
+
+    fn eval_conditions(
+        &self,
+        conditions: &[Condition],
+        scope: &mut Scope,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        for condition in conditions {
+            let condition_matches = self
+                .engine
+                .eval_ast_with_scope::<bool>(scope, &condition.ast)?;
+
+            // if any conditions don't match, return early
+            if !condition_matches {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    pub fn eval_expression(
+        &self,
+        expression: &str,
+        scope: &mut Scope,
+    ) -> Result<Dynamic, Box<dyn std::error::Error>> {
+        let condition = Condition::parse(expression.to_string())?;
+
+        Ok(self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(scope, &condition.ast)?)
+    }
+
+    // NOTE: groups are evaluated in order (sources in config order, files
+    // alphabetically within each source, groups in document order, included
+    // groups before the including group), with the vars of each matching group
+    // merged before evaluating the conditions of any later groups (a group's
+    // own vars are not visible to its own conditions)
+    pub fn filter_files_to_matching_groups(
+        &self,
+        files: &[state::File],
+    ) -> Result<Vec<state::Group>, Box<dyn std::error::Error>> {
+        // NOTE: global vars are restored after (group vars only apply while resolving)
+        let global_vars =
+            self.vars.read().expect("evaluator vars lock").clone();
+
+        self.with_vars(global_vars, || self.filter_groups_in_order(files))
+    }
+
+    pub fn set_vars(&self, vars: Mapping) {
+        *self.vars.write().expect("evaluator vars lock") = vars;
+    }
+
+    // NOTE: evaluates with the given vars, restoring the current vars after
+    pub fn with_vars<T>(&self, vars: Mapping, f: impl FnOnce() -> T) -> T {
+        let previous_vars = std::mem::replace(
+            &mut *self.vars.write().expect("evaluator vars lock"),
+            vars,
+        );
+
+        let result = f();
+
+        *self.vars.write().expect("evaluator vars lock") = previous_vars;
+
+        result
+    }
+
+    fn filter_groups_in_order(
+        &self,
+        files: &[state::File],
+    ) -> Result<Vec<state::Group>, Box<dyn std::error::Error>> {
+        let mut matching_groups = vec![];
+
+        for file in files {
+            for group in &file.groups {
+                match self.eval_conditions(&group.when, &mut Scope::new()) {
+                    Ok(false) => (),
+                    Ok(true) => {
+                        // NOTE: var sources are only evaluated for matching groups
+                        let mut group = group.clone();
+                        let sourced_vars =
+                            group.var_sources.resolve().map_err(|e| {
+                                format!("{e}: in {}", file.path.display())
+                            })?;
+                        group.vars.extend(sourced_vars);
+
+                        merge_vars(
+                            &mut self
+                                .vars
+                                .write()
+                                .expect("evaluator vars lock"),
+                            group.vars.clone(),
+                        );
+                        matching_groups.push(group);
+                    }
+                    Err(e) => Err(format!(
+                        "{}: in conditions {:?} of {}",
+                        e,
+                        group.when,
+                        file.path.display()
+                    ))?,
+                }
+            }
+        }
+
+        Ok(matching_groups)
+    }
+
+    pub fn filter_plugin_partials(
+        &self,
+        partials: Vec<PluginDefinitionPartial>,
+    ) -> Result<Vec<PluginDefinitionPartial>, Box<dyn std::error::Error>> {
+        let mut filtered_partials = Vec::new();
+
+        for partial in partials {
+            let when = partial.when.clone().unwrap_or_default();
+
+            match self.eval_conditions(&when, &mut Scope::new()) {
+                Ok(true) => {
+                    filtered_partials.push(partial);
+                }
+                Err(e) => Err(e)?,
+                _ => (),
+            }
+        }
+
+        Ok(filtered_partials)
+    }
+
+    pub fn filter_plugins(
+        &self,
+        plugins: Vec<Plugin>,
+    ) -> Result<Vec<Plugin>, Box<dyn std::error::Error>> {
+        let mut filtered_plugins = Vec::new();
+
+        for plugin in plugins {
+            match self
+                .eval_conditions(&plugin.definition.when, &mut Scope::new())
+            {
+                Ok(true) => {
+                    filtered_plugins.push(plugin);
+                }
+                Err(e) => Err(e)?,
+                _ => (),
+            }
+        }
+
+        Ok(filtered_plugins)
+    }
+
+    pub fn match_states_to_plugins(
+        &self,
+        declarations: &HashMap<String, state::Declaration>,
+        plugins: &[Plugin],
+    ) -> Result<ExecutionSets, Box<dyn std::error::Error>> {
+        // NOTE: Plugin is hashed by path only, so the (interior mutable) condition ASTs don't matter
+        #[allow(clippy::mutable_key_type)]
+        let mut execution_sets: HashMap<Plugin, Vec<DeclaredState>> =
+            HashMap::new();
+
+        for declaration in declarations.values() {
+            for state in declaration.states.clone() {
+                let mut scope = new_state_scope(&declaration.name, &state)?;
+
+                // TODO: clean up this code
+                let mut matching_plugin = None;
+                for plugin in plugins {
+                    // NOTE: vars only plugins don't provision anything
+                    let Some(provision) = &plugin.definition.provision else {
+                        continue;
+                    };
+
+                    match self.eval_conditions(&provision.when, &mut scope) {
+                        Ok(true) => {
+                            matching_plugin = Some(plugin);
+                            break;
+                        }
+                        Err(e) => Err(e)?,
+                        _ => (),
+                    }
+                }
+                if let Some(plugin) = matching_plugin {
+                    let declared_state = DeclaredState {
+                        declaration: declaration.name.clone(),
+                        state,
+                    };
+                    if let Some((_, v)) =
+                        execution_sets.iter_mut().find(|(p, _)| *p == plugin)
+                    {
+                        v.push(declared_state);
+                    } else {
+                        execution_sets
+                            .insert(plugin.clone(), vec![declared_state]);
+                    }
+                } else {
+                    // TODO: would prefer to handle the logging for this in provision
+                    println!(
+                        "unmatched: {}: {}",
+                        declaration.name,
+                        redact(&format!("{state:?}"))
+                    );
+                }
+            }
+        }
+
+        Ok(execution_sets.into_iter().collect())
+    }
+
+    pub fn filter_execution_sets(
+        &self,
+        execution_sets: &mut ExecutionSets,
+        filter: &Condition,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let declaration_names = execution_sets
+            .iter()
+            .flat_map(|(_, states)| states.iter().map(|s| &s.declaration))
+            .unique()
+            .sorted()
+            .cloned()
+            .collect::<Vec<_>>();
+        let plugin_names = execution_sets
+            .iter()
+            .map(|(p, _)| &p.definition.name)
+            .unique()
+            .sorted()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // filter execution_sets
+        // NOTE: errors are treated as the filter not matching (ie. `state.name` on string states), but are collected so they can be reported
+        let mut errors: Vec<String> = vec![];
+        for (plugin, states) in execution_sets.iter_mut() {
+            let plugin_info = PluginInfo::from(&*plugin);
+            let mut matching_states = Vec::with_capacity(states.len());
+            for state in states.drain(..) {
+                let mut scope =
+                    new_state_scope(&state.declaration, &state.state)?;
+                scope.push_constant("plugin", plugin_info.clone());
+
+                match self
+                    .engine
+                    .eval_ast_with_scope::<bool>(&mut scope, &filter.ast)
+                {
+                    Ok(true) => matching_states.push(state),
+                    Ok(false) => (),
+                    Err(e) => errors.push(e.to_string()),
+                }
+            }
+            *states = matching_states;
+        }
+
+        // remove any empty execution sets
+        execution_sets.retain(|(_, states)| !states.is_empty());
+
+        let error_summary = errors
+            .into_iter()
+            .counts()
+            .into_iter()
+            .sorted()
+            .map(|(e, count)| format!("\n\t- {e} (for {count} states)"))
+            .collect::<String>();
+
+        if execution_sets.is_empty() {
+            let mut message = format!("filter matched no states: {filter}");
+            if !error_summary.is_empty() {
+                message.push_str(&format!("\nerrors:{error_summary}"));
+            }
+            message.push_str(&format!(
+                "\navailable declarations:\n\t- {}",
+                declaration_names.join("\n\t- ")
+            ));
+            message.push_str(&format!(
+                "\navailable plugins:\n\t- {}",
+                plugin_names.join("\n\t- ")
+            ));
+
+            return Err(message.into());
+        }
+
+        if !error_summary.is_empty() {
+            println!(
+                "{}",
+                style(format!(
+                    "errors while filtering (treated as not matching):{error_summary}"
+                ))
+                .yellow()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+// NOTE: the scope used for provision conditions (and filters)
+pub fn new_state_scope(
+    declaration: &str,
+    state: &Value,
+) -> Result<Scope<'static>, Box<dyn std::error::Error>> {
+    let mut scope = Scope::new();
+    scope.push_constant("declaration", declaration.to_string());
+    scope.push_constant_dynamic("state", to_dynamic(state)?);
+
+    Ok(scope)
+}
+
+// TODO: really should be fixing the Value type...
+pub type ExecutionSets = Vec<(Plugin, Vec<DeclaredState>)>;
+
+// TODO: rename?
+#[derive(Serialize, Deserialize, Debug, Hash, Clone, PartialEq, Eq)]
+pub struct DeclaredState {
+    pub declaration: String,
+    pub state: Value,
+}
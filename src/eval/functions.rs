@@ -0,0 +1,287 @@
+use faccess::{AccessMode, PathExt};
+use glob::Pattern;
+use rhai::{Dynamic, Engine, EvalAltResult};
+use serde_yml::{Mapping, Value};
+use std::{
+    cmp::Ordering,
+    env,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+pub fn register_functions(engine: &mut Engine, vars: &Arc<RwLock<Mapping>>) {
+    // system
+    engine.register_fn("command_exists", command_exists);
+    engine.register_fn("path_exists", path_exists);
+    engine.register_fn("env", env_var);
+    engine.register_fn("env", env_var_or);
+
+    // versions
+    engine.register_fn("version_gt", |a: &str, b: &str| {
+        compare_versions(a, b).is_gt()
+    });
+    engine.register_fn("version_gte", |a: &str, b: &str| {
+        compare_versions(a, b).is_ge()
+    });
+    engine.register_fn("version_lt", |a: &str, b: &str| {
+        compare_versions(a, b).is_lt()
+    });
+    engine.register_fn("version_lte", |a: &str, b: &str| {
+        compare_versions(a, b).is_le()
+    });
+    engine.register_fn("version_eq", |a: &str, b: &str| {
+        compare_versions(a, b).is_eq()
+    });
+
+    // strings
+    engine.register_fn("glob_match", glob_match);
+
+    // vars
+    let role_vars = vars.clone();
+    engine.register_fn("has_role", move |role: &str| {
+        match role_vars.read().expect("evaluator vars lock").get("roles") {
+            Some(Value::Sequence(roles)) => {
+                roles.iter().any(|r| r.as_str() == Some(role))
+            }
+            _ => false,
+        }
+    });
+}
+
+fn command_exists(command: &str) -> bool {
+    let is_executable = |path: PathBuf| {
+        path.is_file() && path.access(AccessMode::EXECUTE).is_ok()
+    };
+
+    // paths are checked directly
+    if command.contains(std::path::is_separator) {
+        return is_executable(PathBuf::from(
+            shellexpand::tilde(command).as_ref(),
+        ));
+    }
+
+    env::var_os("PATH").is_some_and(|paths| {
+        env::split_paths(&paths).any(|dir| {
+            is_executable(
+                dir.join(format!("{command}{}", env::consts::EXE_SUFFIX)),
+            )
+        })
+    })
+}
+
+fn path_exists(path: &str) -> bool {
+    PathBuf::from(shellexpand::tilde(path).as_ref()).exists()
+}
+
+fn env_var(name: &str) -> Dynamic {
+    env::var(name).map_or(Dynamic::UNIT, Dynamic::from)
+}
+
+fn env_var_or(name: &str, default: &str) -> String {
+    env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+fn glob_match(value: &str, pattern: &str) -> Result<bool, Box<EvalAltResult>> {
+    let pattern = Pattern::new(pattern)
+        .map_err(|e| format!("{e}: in glob pattern: {pattern}"))?;
+
+    Ok(pattern.matches(value))
+}
+
+// NOTE: compares dot separated components numerically when possible (ie. 14.10 > 14.9)
+// missing components are treated as 0 (ie. 14 == 14.0)
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let a = a
+        .trim()
+        .trim_start_matches('v')
+        .split('.')
+        .collect::<Vec<_>>();
+    let b = b
+        .trim()
+        .trim_start_matches('v')
+        .split('.')
+        .collect::<Vec<_>>();
+
+    for i in 0..a.len().max(b.len()) {
+        let a_part = a.get(i).copied().unwrap_or("0");
+        let b_part = b.get(i).copied().unwrap_or("0");
+
+        let ordering = compare_version_components(a_part, b_part);
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+
+    Ordering::Equal
+}
+
+// NOTE: the leading number is compared first, then the rest of the component
+// the same way (ie. 90-microsoft < 153, 0-45-generic < 0-100-generic), with
+// non-numeric parts compared as strings
+fn compare_version_components(a: &str, b: &str) -> Ordering {
+    let a_parts = version_component_parts(a);
+    let b_parts = version_component_parts(b);
+
+    for (a_part, b_part) in a_parts.iter().zip(&b_parts) {
+        let ordering = match (a_part.parse::<u64>(), b_part.parse::<u64>()) {
+            (Ok(a_num), Ok(b_num)) => a_num.cmp(&b_num),
+            _ => a_part.cmp(b_part),
+        };
+
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+
+    a_parts.len().cmp(&b_parts.len())
+}
+
+// NOTE: splits into runs of digits and non-digits (ie. 0-45-generic => 0, -, 45, -generic)
+fn version_component_parts(component: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut start = 0;
+    for (i, c) in component.char_indices().skip(1) {
+        let previous = component[..i].chars().next_back();
+        if previous.is_some_and(|p| p.is_ascii_digit() != c.is_ascii_digit()) {
+            parts.push(&component[start..i]);
+            start = i;
+        }
+    }
+    if start < component.len() {
+        parts.push(&component[start..]);
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Evaluator;
+    use rhai::Scope;
+
+    #[test]
+    fn compare_versions_numerically() {
+        assert_eq!(compare_versions("14.10", "14.9"), Ordering::Greater);
+        assert_eq!(compare_versions("14.9", "14.10"), Ordering::Less);
+        assert_eq!(compare_versions("1.2.3", "1.2.3"), Ordering::Equal);
+    }
+
+    #[test]
+    fn compare_versions_missing_components_are_zero() {
+        assert_eq!(compare_versions("14", "14.0"), Ordering::Equal);
+        assert_eq!(compare_versions("14.0.0", "14"), Ordering::Equal);
+        assert_eq!(compare_versions("14.0.1", "14"), Ordering::Greater);
+    }
+
+    #[test]
+    fn compare_versions_leading_v() {
+        assert_eq!(compare_versions("v1.2", "1.2"), Ordering::Equal);
+        assert_eq!(compare_versions("v1.10", "v1.9"), Ordering::Greater);
+    }
+
+    #[test]
+    fn compare_versions_non_numeric_parts() {
+        // NOTE: compared as strings when either part isn't a number
+        assert_eq!(
+            compare_versions("1.0.beta", "1.0.alpha"),
+            Ordering::Greater
+        );
+        assert_eq!(compare_versions("1.2.rc1", "1.2.rc1"), Ordering::Equal);
+        assert_eq!(compare_versions("1.x", "1.1"), Ordering::Greater);
+    }
+
+    #[test]
+    fn compare_versions_numeric_prefix() {
+        assert_eq!(
+            compare_versions("5.15.90-microsoft-standard-WSL2", "5.15.153"),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare_versions("5.15.153.1-microsoft-standard-WSL2", "5.15.90"),
+            Ordering::Greater
+        );
+        assert_eq!(
+            compare_versions("6.8.0-45-generic", "6.8.0-100-generic"),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare_versions("6.8.0-45-generic", "6.8"),
+            Ordering::Greater
+        );
+        assert_eq!(
+            compare_versions("6.8.0-45-generic", "6.10"),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare_versions("6.8.0-45-generic", "6.8.0-45-generic"),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn glob_match_patterns() {
+        assert!(glob_match("build-01", "build-*").unwrap());
+        assert!(glob_match("laptop", "lap?op").unwrap());
+        assert!(!glob_match("laptop", "build-*").unwrap());
+    }
+
+    #[test]
+    fn glob_match_invalid_pattern() {
+        let error = glob_match("laptop", "[").unwrap_err();
+
+        assert!(error.to_string().contains("in glob pattern: ["));
+    }
+
+    #[test]
+    fn env_var_with_and_without_default() {
+        env::set_var("NK_TEST_FUNCTIONS_ENV", "value");
+
+        assert_eq!(
+            env_var("NK_TEST_FUNCTIONS_ENV").into_string().unwrap(),
+            "value"
+        );
+        assert_eq!(env_var_or("NK_TEST_FUNCTIONS_ENV", "default"), "value");
+        assert!(env_var("NK_TEST_FUNCTIONS_UNSET").is_unit());
+        assert_eq!(env_var_or("NK_TEST_FUNCTIONS_UNSET", "default"), "default");
+    }
+
+    #[test]
+    fn command_exists_on_path() {
+        assert!(command_exists("sh"));
+        assert!(!command_exists("nk-test-command-that-does-not-exist"));
+    }
+
+    #[test]
+    fn command_exists_with_separator() {
+        assert!(command_exists("/bin/sh"));
+        assert!(!command_exists("/nk-test/command-that-does-not-exist"));
+        // NOTE: directories aren't commands
+        assert!(!command_exists("/bin/"));
+    }
+
+    #[test]
+    fn path_exists_with_tilde() {
+        assert!(path_exists("~"));
+        assert!(!path_exists("~/nk-test-path-that-does-not-exist"));
+    }
+
+    #[test]
+    fn has_role_sees_vars_set_after_new() {
+        let evaluator = Evaluator::new(Mapping::new());
+        let has_work_role = || {
+            evaluator
+                .eval_expression(r#"has_role("work")"#, &mut Scope::new())
+                .unwrap()
+                .as_bool()
+                .unwrap()
+        };
+        assert!(!has_work_role());
+
+        let mut vars = Mapping::new();
+        vars.insert("roles".into(), vec![Value::from("work")].into());
+        evaluator.set_vars(vars);
+
+        assert!(has_work_role());
+    }
+}
@@ -0,0 +1,7 @@
+mod evaluator;
+mod functions;
+mod plugin_info;
+
+pub use self::evaluator::*;
+pub use self::functions::*;
+pub use self::plugin_info::*;
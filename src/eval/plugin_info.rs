@@ -0,0 +1,38 @@
+use crate::plugins::Plugin;
+use rhai::{Dynamic, Engine};
+
+// NOTE: exposed to filters as `plugin`, compares equal to the plugin name (ie. `plugin == "homebrew"`)
+#[derive(Debug, Clone)]
+pub struct PluginInfo {
+    pub name: String,
+    pub path: String,
+    pub version: Option<String>,
+    pub config_index: usize,
+}
+
+impl From<&Plugin> for PluginInfo {
+    fn from(plugin: &Plugin) -> Self {
+        Self {
+            name: plugin.definition.name.clone(),
+            path: plugin.path.to_string_lossy().to_string(),
+            version: plugin.version.clone(),
+            config_index: plugin.config_index,
+        }
+    }
+}
+
+pub fn register_plugin_info(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<PluginInfo>("Plugin")
+        .register_get("name", |p: &mut PluginInfo| p.name.clone())
+        .register_get("path", |p: &mut PluginInfo| p.path.clone())
+        .register_get("version", |p: &mut PluginInfo| {
+            p.version.clone().map_or(Dynamic::UNIT, Dynamic::from)
+        })
+        .register_get("config_index", |p: &mut PluginInfo| {
+            i64::try_from(p.config_index).unwrap_or(i64::MAX)
+        })
+        .register_fn("==", |p: &mut PluginInfo, name: &str| p.name == name)
+        .register_fn("!=", |p: &mut PluginInfo, name: &str| p.name != name)
+        .register_fn("to_string", |p: &mut PluginInfo| p.name.clone());
+}
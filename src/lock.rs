@@ -0,0 +1,62 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+pub fn nk_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = PathBuf::from_str(&shellexpand::tilde("~/.nk"))?;
+    fs::create_dir_all(&path)
+        .map_err(|e| format!("{e}: {}", path.display()))?;
+
+    Ok(path)
+}
+
+// NOTE: advisory lock on ~/.nk, held by nk commands while they modify anything
+// in it (globals, plugins), so concurrent runs don't clobber each other. It's
+// released when dropped, and must not be acquired again while held (the lock
+// is per open file, so that would block forever)
+pub struct NkLock {
+    _file: File,
+}
+
+impl NkLock {
+    pub fn acquire() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = nk_dir()?.join(".lock");
+        let file = File::create(&path)
+            .map_err(|e| format!("{e}: {}", path.display()))?;
+
+        if file.try_lock().is_err() {
+            eprintln!("waiting for another nk process to finish...");
+            file.lock().map_err(|e| {
+                format!("{e}: while locking {}", path.display())
+            })?;
+        }
+
+        Ok(Self { _file: file })
+    }
+}
+
+// NOTE: writes to a temporary file in the same directory, then renames it over
+// the destination, so readers never see a partially written file
+pub fn write_atomically(
+    path: &Path,
+    contents: &str,
+    _lock: &NkLock,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("invalid path: {}", path.display()))?;
+    let mut temp_name = file_name.to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    fs::write(&temp_path, contents)
+        .and_then(|()| fs::rename(&temp_path, path))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temp_path);
+            format!("{e}: {}", path.display())
+        })?;
+
+    Ok(())
+}
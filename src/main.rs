@@ -2,11 +2,13 @@ mod args;
 mod commands;
 mod config;
 mod eval;
+mod lock;
 mod merge;
 mod plugins;
 mod render;
 mod resolve;
 mod root;
+mod secrets;
 mod sort;
 mod state;
 mod traits;
@@ -14,17 +16,20 @@ mod utils;
 mod vars;
 
 use args::{Arguments, Commands};
-use args::{PluginSubcommand, VarSubcommand};
+use args::{MachineSubcommand, PluginSubcommand, VarSubcommand};
 use clap::CommandFactory;
 use clap::Parser;
-use commands::{completion, helper, link, pack, provision, resolve, var_set};
+use commands::{
+    completion, eval, helper, link, machine_list, machine_show, pack,
+    provision, resolve, var_edit, var_get, var_list, var_set, var_unset,
+};
 use config::Config;
 use std::process::ExitCode;
 
 #[tokio::main]
 async fn main() -> ExitCode {
     if let Err(err) = run().await {
-        eprintln!("nk: {}", err);
+        eprintln!("nk: {}", secrets::redact(&err.to_string()));
         return ExitCode::FAILURE;
     }
     ExitCode::SUCCESS
@@ -38,9 +43,18 @@ async fn run() -> Result<(), Box<dyn std::error::Error>> {
     match arguments.command {
         Some(Commands::Provision(args)) => provision(args, config?).await,
         Some(Commands::Resolve(args)) => resolve(args, config?).await,
+        Some(Commands::Eval(args)) => eval(args, config).await,
         Some(Commands::Completion(args)) => completion(&args, &mut cmd),
         Some(Commands::Var(subcommand)) => match subcommand {
-            VarSubcommand::Set(args) => var_set(args),
+            VarSubcommand::Get(args) => var_get(args, config).await,
+            VarSubcommand::Set(args) => var_set(args, config),
+            VarSubcommand::Unset(args) => var_unset(args),
+            VarSubcommand::List => var_list(config).await,
+            VarSubcommand::Edit => var_edit(),
+        },
+        Some(Commands::Machine(subcommand)) => match subcommand {
+            MachineSubcommand::List => machine_list(config?),
+            MachineSubcommand::Show(args) => machine_show(args, config?),
         },
         Some(Commands::Plugin(subcommand)) => match subcommand {
             PluginSubcommand::Link(args) => link(&args),
@@ -1,5 +1,10 @@
-use crate::state::{self, ResolvedGroup};
-use serde_yml::Value;
+use crate::{
+    eval::{DeclaredState, ExecutionSets},
+    state::{self, ResolvedGroup},
+};
+use serde::Deserialize;
+use serde_yml::{value::Tag, Mapping, Value};
+use std::collections::HashSet;
 
 pub fn merge_plugin_dependencies(
     mut a: ResolvedGroup,
@@ -8,7 +13,7 @@ pub fn merge_plugin_dependencies(
     let name = b.name.clone();
     let declaration = match a.declarations.remove(&b.name) {
         Some(d) => merge_declarations(d, b),
-        None => b,
+        None => dedupe_states(b),
     };
 
     a.declarations.insert(name, declaration);
@@ -17,49 +22,561 @@ pub fn merge_plugin_dependencies(
 }
 
 pub fn merge_groups(mut a: ResolvedGroup, b: state::Group) -> ResolvedGroup {
+    a.record_expression_paths(&b);
+
     for (k, v) in b.declarations {
-        let declaration = match a.declarations.remove(&k) {
-            Some(d) => merge_declarations(d, v),
-            None => v,
+        let strategy = b.declaration_strategies.get(&k).copied();
+        let declaration = match (a.declarations.remove(&k), strategy) {
+            (Some(d), _) => merge_declarations_with(d, v, strategy),
+            // NOTE: nothing to remove from
+            (None, Some(MergeStrategy::Remove)) => continue,
+            (None, _) => dedupe_states(v),
         };
 
-        a.declarations.insert(k, declaration);
+        // NOTE: declarations with every state removed are dropped entirely
+        if !declaration.states.is_empty() {
+            a.declarations.insert(k, declaration);
+        }
     }
 
-    for (k, v) in b.vars {
-        let var = match a.vars.remove(&k) {
-            Some(d) => merge_values(d, v),
-            None => v,
-        };
-
-        a.vars.insert(k, var);
-    }
+    merge_vars(&mut a.vars, b.vars);
 
     a
 }
 
+pub fn merge_vars(a: &mut Mapping, b: Mapping) {
+    // NOTE: existing vars are merged in place to preserve their order
+    for (k, v) in b {
+        let (strategy, v) = split_merge_strategy(v);
+        match (a.get_mut(&k), strategy) {
+            (Some(var), strategy) => {
+                *var = merge_values(std::mem::take(var), v, strategy);
+            }
+            // NOTE: nothing to remove from
+            (None, Some(MergeStrategy::Remove)) => (),
+            (None, strategy) => {
+                a.insert(k, strip_split_merge_tags(strategy, v));
+            }
+        }
+    }
+}
+
 fn merge_declarations(
+    a: state::Declaration,
+    b: state::Declaration,
+) -> state::Declaration {
+    merge_declarations_with(a, b, None)
+}
+
+// NOTE: states are appended by default, and identical states are only kept once
+fn merge_declarations_with(
     mut a: state::Declaration,
-    mut b: state::Declaration,
+    b: state::Declaration,
+    strategy: Option<MergeStrategy>,
 ) -> state::Declaration {
-    a.states.append(&mut b.states);
-    a
+    a.states = merge_sequences(
+        a.states,
+        b.states,
+        strategy.unwrap_or(MergeStrategy::Append),
+    );
+
+    dedupe_states(a)
+}
+
+// NOTE: keeps the first of any identical states
+fn dedupe_states(mut declaration: state::Declaration) -> state::Declaration {
+    let mut seen = HashSet::with_capacity(declaration.states.len());
+    declaration
+        .states
+        .retain(|state| seen.insert(state.clone()));
+    declaration
 }
 
-fn merge_values(a: Value, b: Value) -> Value {
-    match (a, b) {
-        (Value::Mapping(mut a), Value::Mapping(b)) => {
-            for (k, v) in b {
-                let var = match a.remove(&k) {
-                    Some(d) => merge_values(d, v),
-                    None => v,
-                };
+// NOTE: mapping states of the same declaration with the same value for the
+// plugin's identity key are merged into the first of them
+pub fn merge_states_by_key(
+    execution_sets: &mut ExecutionSets,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut conflicts = vec![];
+    for (plugin, states) in execution_sets.iter_mut() {
+        let Some(key) = &plugin.definition.key else {
+            continue;
+        };
+
+        let mut merged: Vec<DeclaredState> = Vec::with_capacity(states.len());
+        for state in states.drain(..) {
+            let existing = state.state.get(key).and_then(|identity| {
+                merged.iter_mut().find(|s| {
+                    s.declaration == state.declaration
+                        && s.state.get(key) == Some(identity)
+                })
+            });
+
+            match (existing, state.state) {
+                (
+                    Some(DeclaredState {
+                        state: Value::Mapping(a),
+                        ..
+                    }),
+                    Value::Mapping(b),
+                ) => {
+                    let identity = display_name(&a[key.as_str()]);
 
-                a.insert(k, var);
+                    for (k, v) in b {
+                        match a.get(&k) {
+                            Some(a_v) if *a_v != v => {
+                                conflicts.push(format!(
+                                    "{}: {key}: {identity}: {}: {} != {}",
+                                    state.declaration,
+                                    display_name(&k),
+                                    display_value(a_v),
+                                    display_value(&v),
+                                ));
+                            }
+                            Some(_) => (),
+                            None => {
+                                a.insert(k, v);
+                            }
+                        }
+                    }
+                }
+                (_, s) => merged.push(DeclaredState {
+                    declaration: state.declaration,
+                    state: s,
+                }),
             }
+        }
+        *states = merged;
+    }
+
+    if conflicts.is_empty() {
+        Ok(())
+    } else {
+        Err(
+            format!("conflicting states:\n\t- {}", conflicts.join("\n\t- "))
+                .into(),
+        )
+    }
+}
+
+fn display_name(value: &Value) -> String {
+    value
+        .as_str()
+        .map_or_else(|| display_value(value), str::to_string)
+}
+
+fn display_value(value: &Value) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| format!("{value:?}"))
+}
+
+// NOTE: the strategy for merging a (later) value into an existing one, set
+// with a yaml tag on the later value (ie. `roles: !append [work]`), or via the
+// group's `merge:` section
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeStrategy {
+    // NOTE: mappings are still merged recursively by default, use an explicit
+    // !replace to replace them entirely
+    #[default]
+    Replace,
+    Append,
+    Prepend,
+    UniqueAppend,
+    Remove,
+}
+
+impl MergeStrategy {
+    pub const fn tag(self) -> &'static str {
+        match self {
+            Self::Replace => "replace",
+            Self::Append => "append",
+            Self::Prepend => "prepend",
+            Self::UniqueAppend => "unique-append",
+            Self::Remove => "remove",
+        }
+    }
+
+    fn from_tag(tag: &Tag) -> Option<Self> {
+        [
+            Self::Replace,
+            Self::Append,
+            Self::Prepend,
+            Self::UniqueAppend,
+            Self::Remove,
+        ]
+        .into_iter()
+        .find(|s| *tag == s.tag())
+    }
+}
+
+// NOTE: separates the merge strategy tag (if any) from the value
+pub fn split_merge_strategy(value: Value) -> (Option<MergeStrategy>, Value) {
+    match value {
+        Value::Tagged(v) => match MergeStrategy::from_tag(&v.tag) {
+            Some(strategy) => (Some(strategy), v.value),
+            None => (None, Value::Tagged(v)),
+        },
+        v => (None, v),
+    }
+}
+
+// NOTE: removes merge strategy tags from values which have nothing to merge into
+fn strip_merge_tags(value: Value) -> Value {
+    let (strategy, value) = split_merge_strategy(value);
+    strip_split_merge_tags(strategy, value)
+}
+
+fn strip_split_merge_tags(
+    strategy: Option<MergeStrategy>,
+    value: Value,
+) -> Value {
+    match (strategy, value) {
+        // NOTE: single values are treated as a list of one
+        (
+            Some(
+                MergeStrategy::Append
+                | MergeStrategy::Prepend
+                | MergeStrategy::UniqueAppend,
+            ),
+            v @ (Value::Null
+            | Value::Bool(_)
+            | Value::Number(_)
+            | Value::String(_)),
+        ) => Value::Sequence(vec![v]),
+        (_, Value::Mapping(mut v)) => {
+            v.retain(|_, v| {
+                !matches!(
+                    v,
+                    Value::Tagged(t)
+                        if MergeStrategy::from_tag(&t.tag)
+                            == Some(MergeStrategy::Remove)
+                )
+            });
+            Value::Mapping(
+                v.into_iter()
+                    .map(|(k, v)| (k, strip_merge_tags(v)))
+                    .collect(),
+            )
+        }
+        (_, v) => v,
+    }
+}
+
+fn merge_values(a: Value, b: Value, strategy: Option<MergeStrategy>) -> Value {
+    match (strategy, a, b) {
+        (None, Value::Mapping(mut a), Value::Mapping(b)) => {
+            merge_vars(&mut a, b);
             Value::Mapping(a)
         }
-        // TODO: decide how we want to handle lists...
-        (_, b) => b,
+        // NOTE: single values are treated as a list of one (ie. `roles: !append work`)
+        (Some(strategy), Value::Sequence(a), b)
+            if strategy != MergeStrategy::Replace =>
+        {
+            let b = match b {
+                Value::Sequence(b) => b,
+                b => vec![b],
+            };
+            Value::Sequence(merge_sequences(a, b, strategy))
+        }
+        // NOTE: only lists can be removed from
+        (Some(MergeStrategy::Remove), a, _) => a,
+        // NOTE: anything else (including mismatched types) is replaced
+        (_, _, b) => strip_merge_tags(b),
+    }
+}
+
+fn merge_sequences(
+    mut a: Vec<Value>,
+    mut b: Vec<Value>,
+    strategy: MergeStrategy,
+) -> Vec<Value> {
+    match strategy {
+        MergeStrategy::Replace => b,
+        MergeStrategy::Append => {
+            a.append(&mut b);
+            a
+        }
+        MergeStrategy::Prepend => {
+            b.append(&mut a);
+            b
+        }
+        MergeStrategy::UniqueAppend => {
+            for v in b {
+                if !a.contains(&v) {
+                    a.push(v);
+                }
+            }
+            a
+        }
+        MergeStrategy::Remove => {
+            a.retain(|v| !b.contains(v));
+            a
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml<T: serde::de::DeserializeOwned>(contents: &str) -> T {
+        serde_yml::from_str(contents).unwrap()
+    }
+
+    fn merged(a: &str, b: &str) -> Mapping {
+        let mut vars = yaml(a);
+        merge_vars(&mut vars, yaml(b));
+        vars
+    }
+
+    #[test]
+    fn merge_strategies() {
+        let a = "roles: [a, b]";
+
+        assert_eq!(merged(a, "roles: [b, c]"), yaml("roles: [b, c]"));
+        assert_eq!(merged(a, "roles: !replace [b, c]"), yaml("roles: [b, c]"));
+        assert_eq!(
+            merged(a, "roles: !append [b, c]"),
+            yaml("roles: [a, b, b, c]")
+        );
+        assert_eq!(
+            merged(a, "roles: !prepend [b, c]"),
+            yaml("roles: [b, c, a, b]")
+        );
+        assert_eq!(
+            merged(a, "roles: !unique-append [b, c]"),
+            yaml("roles: [a, b, c]")
+        );
+        assert_eq!(merged(a, "roles: !remove [b]"), yaml("roles: [a]"));
+    }
+
+    #[test]
+    fn mappings_are_merged_unless_replaced() {
+        let a = "git: {name: me, email: me@example.com}";
+
+        assert_eq!(
+            merged(a, "git: {email: me@work.example.com}"),
+            yaml("git: {name: me, email: me@work.example.com}")
+        );
+        assert_eq!(
+            merged(a, "git: !replace {email: me@work.example.com}"),
+            yaml("git: {email: me@work.example.com}")
+        );
+    }
+
+    #[test]
+    fn nested_mappings_containing_lists() {
+        let a = "m: {a: [1], b: [1, 2], c: x}";
+
+        assert_eq!(
+            merged(a, "m: {a: !append [2], b: !remove [1]}"),
+            yaml("m: {a: [1, 2], b: [2], c: x}")
+        );
+        assert_eq!(
+            merged(a, "m: {a: [2]}"),
+            yaml("m: {a: [2], b: [1, 2], c: x}")
+        );
+    }
+
+    #[test]
+    fn merge_section() {
+        let group: state::Group = yaml(
+            "
+            vars:
+              roles: [work]
+              packages: [git]
+            merge:
+              roles: append
+              packages: remove
+            ",
+        );
+        let resolved =
+            ResolvedGroup::new(yaml("{roles: [dev], packages: [git, vim]}"));
+
+        let resolved = merge_groups(resolved, group);
+
+        assert_eq!(
+            resolved.vars,
+            yaml("{roles: [dev, work], packages: [vim]}")
+        );
+    }
+
+    #[test]
+    fn merge_section_requires_var() {
+        let group = serde_yml::from_str::<state::Group>(
+            "
+            vars: {roles: [work]}
+            merge: {packages: append}
+            ",
+        );
+
+        assert!(group
+            .unwrap_err()
+            .to_string()
+            .contains("merge: no var named: packages"));
+    }
+
+    #[test]
+    fn single_value_is_a_list_of_one() {
+        assert_eq!(
+            merged("roles: [a]", "roles: !append b"),
+            yaml("roles: [a, b]")
+        );
+        assert_eq!(
+            merged("roles: [a, b]", "roles: !remove a"),
+            yaml("roles: [b]")
+        );
+        assert_eq!(merged("{}", "roles: !append b"), yaml("roles: [b]"));
+    }
+
+    #[test]
+    fn remove_with_nothing_to_remove_from() {
+        assert_eq!(merged("{}", "roles: !remove [a]"), yaml("{}"));
+        assert_eq!(merged("roles: a", "roles: !remove [a]"), yaml("roles: a"));
+        assert_eq!(
+            merged("m: {}", "m: {a: !remove [1]}"),
+            yaml::<Mapping>("m: {}")
+        );
+    }
+
+    #[test]
+    fn tags_are_stripped_with_nothing_to_merge_into() {
+        assert_eq!(
+            merged("{}", "m: {a: !append [1], b: !replace {c: 1}}"),
+            yaml("m: {a: [1], b: {c: 1}}")
+        );
+        assert_eq!(merged("{}", "roles: !prepend [a]"), yaml("roles: [a]"));
+        assert_eq!(
+            merged("{}", "m: {a: !remove [1], b: 2}"),
+            yaml("m: {b: 2}")
+        );
+    }
+
+    #[test]
+    fn single_declaration_dedupes_states() {
+        let a: state::Group = yaml("packages: [git, vim, git]");
+
+        let resolved = merge_groups(ResolvedGroup::new(Mapping::new()), a);
+
+        assert_eq!(
+            resolved.declarations["packages"].states,
+            yaml::<Vec<Value>>("[git, vim]")
+        );
+    }
+
+    #[test]
+    fn merge_declarations_dedupes_states() {
+        let a: state::Group = yaml("packages: [git, vim]");
+        let b: state::Group = yaml("packages: [vim, curl]");
+
+        let resolved = merge_groups(ResolvedGroup::new(Mapping::new()), a);
+        let resolved = merge_groups(resolved, b);
+
+        assert_eq!(
+            resolved.declarations["packages"].states,
+            yaml::<Vec<Value>>("[git, vim, curl]")
+        );
+    }
+
+    fn execution_sets(key: Option<&str>, states: &str) -> ExecutionSets {
+        let mut definition: crate::plugins::PluginDefinition =
+            yaml("{name: files, executable: plugin, schema: {}}");
+        definition.key = key.map(str::to_string);
+        let plugin = crate::plugins::Plugin {
+            path: "plugins/files".into(),
+            definition,
+            config_index: 0,
+            version: None,
+        };
+
+        vec![(plugin, yaml(states))]
+    }
+
+    fn states(execution_sets: &ExecutionSets) -> Vec<DeclaredState> {
+        execution_sets[0].1.clone()
+    }
+
+    #[test]
+    fn merge_states_by_identity_key() {
+        let mut sets = execution_sets(
+            Some("path"),
+            "[
+                {declaration: files, state: {path: ~/.zshrc, link: a}},
+                {declaration: files, state: {path: ~/.vimrc, link: b}},
+                {declaration: files, state: {path: ~/.zshrc, mode: '0644'}},
+                {declaration: dirs, state: {path: ~/.zshrc}},
+            ]",
+        );
+
+        merge_states_by_key(&mut sets).unwrap();
+
+        assert_eq!(
+            states(&sets),
+            yaml::<Vec<DeclaredState>>(
+                "[
+                    {declaration: files, state: {path: ~/.zshrc, link: a, mode: '0644'}},
+                    {declaration: files, state: {path: ~/.vimrc, link: b}},
+                    {declaration: dirs, state: {path: ~/.zshrc}},
+                ]"
+            )
+        );
+    }
+
+    #[test]
+    fn merge_states_by_key_reports_conflicts() {
+        let mut sets = execution_sets(
+            Some("path"),
+            "[
+                {declaration: files, state: {path: ~/.zshrc, link: a}},
+                {declaration: files, state: {path: ~/.zshrc, link: b}},
+            ]",
+        );
+
+        let error = merge_states_by_key(&mut sets).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "conflicting states:\n\t- files: path: ~/.zshrc: link: \"a\" != \"b\""
+        );
+    }
+
+    #[test]
+    fn merge_states_by_key_keeps_non_mapping_states() {
+        let mut sets = execution_sets(
+            Some("path"),
+            "[
+                {declaration: files, state: ~/.zshrc},
+                {declaration: files, state: ~/.zshrc},
+                {declaration: files, state: {link: a}},
+                {declaration: files, state: {link: a}},
+            ]",
+        );
+
+        merge_states_by_key(&mut sets).unwrap();
+
+        assert_eq!(
+            states(&sets),
+            yaml::<Vec<DeclaredState>>(
+                "[
+                    {declaration: files, state: ~/.zshrc},
+                    {declaration: files, state: ~/.zshrc},
+                    {declaration: files, state: {link: a}},
+                    {declaration: files, state: {link: a}},
+                ]"
+            )
+        );
+    }
+
+    #[test]
+    fn merge_states_without_key_is_a_noop() {
+        let states_yaml = "[
+            {declaration: files, state: {path: ~/.zshrc, link: a}},
+            {declaration: files, state: {path: ~/.zshrc, link: b}},
+        ]";
+        let mut sets = execution_sets(None, states_yaml);
+
+        merge_states_by_key(&mut sets).unwrap();
+
+        assert_eq!(states(&sets), yaml::<Vec<DeclaredState>>(states_yaml));
     }
 }
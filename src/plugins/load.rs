@@ -2,6 +2,7 @@ use super::{Manifest, ManifestAssets, Plugin};
 use crate::{
     config::{Config, PluginSource, Version},
     eval::Evaluator,
+    lock::NkLock,
     vars::{get_system_vars, SystemVars},
 };
 use async_compression::futures::bufread::GzipDecoder;
@@ -130,6 +131,12 @@ pub async fn load_plugins(
     let system_vars = get_system_vars()?;
 
     // download/update remote plugins
+    let lock = config
+        .plugins
+        .iter()
+        .any(|p| matches!(p.source, PluginSource::Github { .. }))
+        .then(NkLock::acquire)
+        .transpose()?;
     for plugin in &config.plugins {
         match &plugin.source {
             PluginSource::Local { .. } => {} // TODO: might want to link local plugins into ~/.nk/plugins/
@@ -172,6 +179,8 @@ pub async fn load_plugins(
         }
     }
 
+    drop(lock);
+
     // load plugins
     let all_plugins = futures::future::join_all(config
         .plugins
@@ -361,21 +370,39 @@ fn get_asset_priority(name: &str, vars: &SystemVars) -> Vec<String> {
     // - chocolatey-windows.tar.gz
     // - chocolatey.tar.gz
 
+    // ie. apt plugin on ubuntu 24.04 (when the distro version is known)
+    // - apt-ubuntu-24.04-x86_64.tar.gz
+    // - apt-ubuntu-x86_64.tar.gz
+    // - ...
+    // - apt-x86_64.tar.gz
+    // - apt-ubuntu-24.04.tar.gz
+    // - apt-ubuntu.tar.gz
+    // - ...
+
     let SystemVars {
         distro,
+        distro_version,
         os,
         family,
         arch,
     } = vars;
 
-    vec![
-        format!("{name}-{distro}-{arch}.tar.gz"),
-        format!("{name}-{os}-{arch}.tar.gz"),
-        format!("{name}-{family}-{arch}.tar.gz"),
-        format!("{name}-{arch}.tar.gz"),
-        format!("{name}-{distro}.tar.gz"),
-        format!("{name}-{os}.tar.gz"),
-        format!("{name}-{family}.tar.gz"),
-        format!("{name}.tar.gz"),
+    let with_version = !distro_version.is_empty();
+    [
+        with_version
+            .then(|| format!("{name}-{distro}-{distro_version}-{arch}.tar.gz")),
+        Some(format!("{name}-{distro}-{arch}.tar.gz")),
+        Some(format!("{name}-{os}-{arch}.tar.gz")),
+        Some(format!("{name}-{family}-{arch}.tar.gz")),
+        Some(format!("{name}-{arch}.tar.gz")),
+        with_version
+            .then(|| format!("{name}-{distro}-{distro_version}.tar.gz")),
+        Some(format!("{name}-{distro}.tar.gz")),
+        Some(format!("{name}-{os}.tar.gz")),
+        Some(format!("{name}-{family}.tar.gz")),
+        Some(format!("{name}.tar.gz")),
     ]
+    .into_iter()
+    .flatten()
+    .collect()
 }
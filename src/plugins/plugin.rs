@@ -1,6 +1,6 @@
 use crate::{
     eval::{DeclaredState, Evaluator},
-    state::{Condition, Declaration, RawDeclaration},
+    state::{Condition, Conditions, Declaration, RawDeclaration},
     utils::{
         deserialize_map_to_map_of_named,
         deserialize_map_to_map_of_named_optional,
@@ -24,9 +24,20 @@ use std::{
 pub struct PluginDefinition {
     pub name: String,
     pub executable: String,
-    pub provision: PluginProvisionDefinition, // TODO: likely this will be optional (once we support var plugins...)
+    // NOTE: plugins must support provision and/or vars
+    pub provision: Option<PluginProvisionDefinition>,
 
-    #[serde_as(deserialize_as = "OneOrMany<_>")]
+    // NOTE: vars plugins are run (via the `vars` verb) before groups are
+    // filtered, with the returned mapping merged into the global vars
+    #[serde(default)]
+    pub vars: bool,
+
+    // NOTE: only plugins which set this receive secrets (in their states or
+    // vars), otherwise secrets are redacted from the vars they're given
+    #[serde(default)]
+    pub secrets: bool,
+
+    #[serde_as(deserialize_as = "Conditions")]
     #[serde(default)]
     pub when: Vec<Condition>,
 
@@ -41,6 +52,11 @@ pub struct PluginDefinition {
     )]
     pub dependencies: HashMap<String, Declaration>,
 
+    // NOTE: identifies mapping states (ie. `key: name`), so multiple states
+    // with the same key (in a declaration) are merged instead of duplicated
+    #[serde(default)]
+    pub key: Option<String>,
+
     pub schema: Value,
 }
 
@@ -51,8 +67,10 @@ pub struct PluginDefinitionPartial {
     pub name: Option<String>,
     pub executable: Option<String>,
     pub provision: Option<PluginProvisionDefinition>,
+    pub vars: Option<bool>,
+    pub secrets: Option<bool>,
 
-    #[serde_as(deserialize_as = "Option<OneOrMany<_>>")]
+    #[serde_as(deserialize_as = "Option<Conditions>")]
     pub when: Option<Vec<Condition>>,
 
     #[serde_as(deserialize_as = "Option<OneOrMany<_>>")]
@@ -64,6 +82,8 @@ pub struct PluginDefinitionPartial {
     )]
     pub dependencies: Option<HashMap<String, Declaration>>,
 
+    pub key: Option<String>,
+
     pub schema: Option<Value>,
 }
 
@@ -71,17 +91,22 @@ impl TryInto<PluginDefinition> for PluginDefinitionPartial {
     type Error = Box<dyn std::error::Error>;
 
     fn try_into(self) -> Result<PluginDefinition, Self::Error> {
+        if self.provision.is_none() && !self.vars.unwrap_or_default() {
+            return Err("missing required field, at least one matching partial must have: provision (or vars: true)".into());
+        }
+
         Ok(PluginDefinition {
             name: self.name.ok_or("missing required field, at least one matching partial must have: name")?,
             executable: self
                 .executable
                 .ok_or("missing required field, at least one matching partial must have: executable")?,
-            provision: self
-                .provision
-                .ok_or("missing required field, at least one matching partial must have: provision")?,
+            provision: self.provision,
+            vars: self.vars.unwrap_or_default(),
+            secrets: self.secrets.unwrap_or_default(),
             when: self.when.unwrap_or_default(),
             after: self.after.unwrap_or_default(),
             dependencies: self.dependencies.unwrap_or_default(),
+            key: self.key,
             schema: self.schema.ok_or("missing required field, at least one matching partial must have: schema")?,
         })
     }
@@ -111,7 +136,7 @@ impl PluginFile {
 #[serde(deny_unknown_fields)]
 // TODO: maybe bad name...
 pub struct PluginProvisionDefinition {
-    #[serde_as(deserialize_as = "OneOrMany<_>")]
+    #[serde_as(deserialize_as = "Conditions")]
     #[serde(default)]
     pub when: Vec<Condition>,
 }
@@ -124,6 +149,8 @@ pub struct Plugin {
     pub path: PathBuf,
     pub definition: PluginDefinition,
     pub config_index: usize,
+    // NOTE: only downloaded plugins have a version
+    pub version: Option<String>,
 }
 
 // TODO: path is good enough for now, but might want to compare more fields
@@ -174,6 +201,12 @@ impl Plugin {
                     if partial.provision.is_some() {
                         acc.provision = partial.provision;
                     }
+                    if partial.vars.is_some() {
+                        acc.vars = partial.vars;
+                    }
+                    if partial.secrets.is_some() {
+                        acc.secrets = partial.secrets;
+                    }
                     if partial.when.is_some() {
                         // TODO: merge instead... (doesn't matter atm, but might later)
                         acc.when = partial.when;
@@ -184,6 +217,9 @@ impl Plugin {
                     if partial.dependencies.is_some() {
                         acc.dependencies = partial.dependencies;
                     }
+                    if partial.key.is_some() {
+                        acc.key = partial.key;
+                    }
                     if partial.schema.is_some() {
                         acc.schema = partial.schema;
                     }
@@ -195,10 +231,18 @@ impl Plugin {
             partial.try_into()?
         };
 
+        // load version (written when downloading the plugin)
+        let version = match std::fs::read_to_string(path.join(".nk_version")) {
+            Ok(v) => Some(v.trim().to_string()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => Err(e)?,
+        };
+
         Ok(Some(Plugin {
             path,
             definition,
             config_index,
+            version,
         }))
     }
 
@@ -237,6 +281,35 @@ impl Plugin {
         Ok(reader.lines())
     }
 
+    pub fn vars(
+        &self,
+        info: &ProvisionInfo,
+    ) -> Result<Mapping, Box<dyn std::error::Error>> {
+        let info_json = serde_json::to_string(info)
+            .expect("ProvisionInfo should never fail to serialize");
+
+        let mut child = self.execute(["vars", info_json.as_str()])?;
+
+        // close stdin (no states to write)
+        drop(child.stdin.take());
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "vars failed with {}:\n{}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            )
+            .into());
+        }
+
+        // NOTE: yaml, so json works as well
+        let vars: Option<Mapping> = serde_yml::from_slice(&output.stdout)
+            .map_err(|e| format!("{e}: vars should output a mapping"))?;
+
+        Ok(vars.unwrap_or_default())
+    }
+
     fn execute<I, S>(
         &self,
         args: I,
@@ -0,0 +1,384 @@
+use handlebars::{
+    handlebars_helper, Context, Handlebars, Helper, HelperDef, HelperResult,
+    JsonValue, Output, RenderContext, RenderError, RenderErrorReason,
+    Renderable, ScopedJson,
+};
+use lazy_static::lazy_static;
+use std::{collections::HashMap, env, path::PathBuf, sync::Mutex};
+
+use crate::utils::command_output;
+
+handlebars_helper!(EnvHelper: |name: str| env::var(name).ok());
+handlebars_helper!(LowerHelper: |value: str| value.to_lowercase());
+handlebars_helper!(UpperHelper: |value: str| value.to_uppercase());
+handlebars_helper!(ReplaceHelper: |value: str, from: str, to: str| {
+    value.replace(from, to)
+});
+handlebars_helper!(PathJoinHelper: |*parts| {
+    parts
+        .iter()
+        .map(|p| p.as_str().map_or_else(|| p.to_string(), ToString::to_string))
+        .fold(PathBuf::new(), |path, part| {
+            path.join(shellexpand::tilde(&part).as_ref())
+        })
+        .to_string_lossy()
+        .to_string()
+});
+
+// NOTE: vars are rendered in multiple passes, so files are cached (for the
+// whole run) instead of being read on every pass (as is command output)
+lazy_static! {
+    static ref READ_FILE_CACHE: Mutex<HashMap<String, String>> =
+        Mutex::new(HashMap::new());
+}
+
+pub fn register_helpers(registry: &mut Handlebars) {
+    registry.register_helper("env", Box::new(EnvHelper));
+    registry.register_helper("lower", Box::new(LowerHelper));
+    registry.register_helper("upper", Box::new(UpperHelper));
+    registry.register_helper("replace", Box::new(ReplaceHelper));
+    registry.register_helper("path_join", Box::new(PathJoinHelper));
+    registry.register_helper("default", Box::new(DefaultHelper));
+    registry.register_helper("json", Box::new(JsonHelper));
+    registry.register_helper("yaml", Box::new(YamlHelper));
+    registry.register_helper("read_file", Box::new(ReadFileHelper));
+    registry.register_helper("command_output", Box::new(CommandOutputHelper));
+
+    // conditionals on vars
+    for (name, var) in [
+        ("if_os", "os"),
+        ("if_distro", "distro"),
+        ("if_family", "family"),
+        ("if_arch", "arch"),
+        ("if_machine", "machine"),
+        ("if_hostname", "hostname"),
+        ("if_role", "roles"),
+    ] {
+        registry.register_helper(name, Box::new(IfVarHelper { var }));
+    }
+}
+
+fn helper_error(helper: &str, message: impl std::fmt::Display) -> RenderError {
+    RenderErrorReason::Other(format!("{helper}: {message}")).into()
+}
+
+fn str_param<'a>(
+    h: &'a Helper,
+    helper: &'static str,
+    index: usize,
+) -> Result<&'a str, RenderError> {
+    h.param(index)
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex(helper, index))?
+        .value()
+        .as_str()
+        .ok_or_else(|| RenderErrorReason::InvalidParamType("string").into())
+}
+
+// NOTE: unlike most helpers, missing values are allowed (even in strict mode)
+// ie. {{default some_var "fallback"}}
+struct DefaultHelper;
+impl HelperDef for DefaultHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let value = h.param(0).map(|p| p.value().clone());
+        let fallback = h
+            .param(1)
+            .ok_or(RenderErrorReason::ParamNotFoundForIndex("default", 1))?
+            .value()
+            .clone();
+
+        Ok(ScopedJson::Derived(match value {
+            None | Some(JsonValue::Null) => fallback,
+            Some(JsonValue::String(s)) if s.is_empty() => fallback,
+            Some(v) => v,
+        }))
+    }
+}
+
+struct JsonHelper;
+impl HelperDef for JsonHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let value = h
+            .param(0)
+            .ok_or(RenderErrorReason::ParamNotFoundForIndex("json", 0))?
+            .value();
+
+        let json = serde_json::to_string(value)
+            .map_err(|e| helper_error("json", e))?;
+
+        Ok(ScopedJson::Derived(JsonValue::String(json)))
+    }
+}
+
+struct YamlHelper;
+impl HelperDef for YamlHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let value = h
+            .param(0)
+            .ok_or(RenderErrorReason::ParamNotFoundForIndex("yaml", 0))?
+            .value();
+
+        let yaml =
+            serde_yml::to_string(value).map_err(|e| helper_error("yaml", e))?;
+
+        Ok(ScopedJson::Derived(JsonValue::String(
+            yaml.trim_end().to_string(),
+        )))
+    }
+}
+
+struct ReadFileHelper;
+impl HelperDef for ReadFileHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let path = str_param(h, "read_file", 0)?;
+        let expanded_path = shellexpand::tilde(path).to_string();
+
+        let cached = READ_FILE_CACHE
+            .lock()
+            .expect("read file cache lock")
+            .get(&expanded_path)
+            .cloned();
+        if let Some(contents) = cached {
+            return Ok(ScopedJson::Derived(JsonValue::String(contents)));
+        }
+
+        let contents = std::fs::read_to_string(&expanded_path)
+            .map_err(|e| helper_error("read_file", format!("{e}: {path}")))?;
+        READ_FILE_CACHE
+            .lock()
+            .expect("read file cache lock")
+            .insert(expanded_path, contents.clone());
+
+        Ok(ScopedJson::Derived(JsonValue::String(contents)))
+    }
+}
+
+// NOTE: runs the command directly (not through a shell)
+// ie. {{command_output "brew" "--prefix"}}
+struct CommandOutputHelper;
+impl HelperDef for CommandOutputHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let program = str_param(h, "command_output", 0)?;
+        let args = (1..h.params().len())
+            .map(|i| str_param(h, "command_output", i))
+            .collect::<Result<Vec<_>, _>>()?;
+        let output = command_output(program, &args)
+            .map_err(|e| helper_error("command_output", e))?;
+
+        Ok(ScopedJson::Derived(JsonValue::String(output)))
+    }
+}
+
+// NOTE: block helper which renders when the var equals (or for lists,
+// contains) any of the given values
+// ie. {{#if_os "macos" "linux"}}...{{else}}...{{/if_os}}
+struct IfVarHelper {
+    var: &'static str,
+}
+impl HelperDef for IfVarHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        r: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let expected = h.params().iter().map(|p| p.value()).collect::<Vec<_>>();
+        let matches = match ctx.data().get(self.var) {
+            Some(JsonValue::Array(values)) => {
+                values.iter().any(|v| expected.contains(&v))
+            }
+            Some(value) => expected.contains(&value),
+            None => false,
+        };
+
+        let template = if matches { h.template() } else { h.inverse() };
+        template.map_or_else(|| Ok(()), |t| t.render(r, ctx, rc, out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(template: &str, data: &JsonValue) -> Result<String, RenderError> {
+        let mut registry = Handlebars::new();
+        registry.set_strict_mode(true);
+        register_helpers(&mut registry);
+
+        registry.render_template(template, data)
+    }
+
+    #[test]
+    fn env_helper() {
+        env::set_var("NK_TEST_HELPERS_ENV", "value");
+
+        assert_eq!(
+            render(r#"{{env "NK_TEST_HELPERS_ENV"}}"#, &json!({})).unwrap(),
+            "value"
+        );
+        assert_eq!(
+            render(r#"{{env "NK_TEST_HELPERS_UNSET"}}"#, &json!({})).unwrap(),
+            ""
+        );
+    }
+
+    #[test]
+    fn path_join_helper() {
+        let data = json!({"home": "/home/user"});
+
+        assert_eq!(
+            render(r#"{{path_join home ".config" "nk"}}"#, &data).unwrap(),
+            "/home/user/.config/nk"
+        );
+    }
+
+    #[test]
+    fn default_helper() {
+        let data = json!({"null": null, "empty": "", "set": "value"});
+
+        assert_eq!(
+            render(r#"{{default missing "fallback"}}"#, &data).unwrap(),
+            "fallback"
+        );
+        assert_eq!(
+            render(r#"{{default null "fallback"}}"#, &data).unwrap(),
+            "fallback"
+        );
+        assert_eq!(
+            render(r#"{{default empty "fallback"}}"#, &data).unwrap(),
+            "fallback"
+        );
+        assert_eq!(
+            render(r#"{{default set "fallback"}}"#, &data).unwrap(),
+            "value"
+        );
+    }
+
+    #[test]
+    fn string_helpers() {
+        let data = json!({"hostname": "Work-Laptop"});
+
+        assert_eq!(render("{{lower hostname}}", &data).unwrap(), "work-laptop");
+        assert_eq!(render("{{upper hostname}}", &data).unwrap(), "WORK-LAPTOP");
+        assert_eq!(
+            render(r#"{{replace hostname "-" "_"}}"#, &data).unwrap(),
+            "Work_Laptop"
+        );
+    }
+
+    #[test]
+    fn json_and_yaml_helpers() {
+        let data = json!({"git": {"email": "me@example.com"}});
+
+        assert_eq!(
+            render("{{{json git}}}", &data).unwrap(),
+            r#"{"email":"me@example.com"}"#
+        );
+        assert_eq!(
+            render("{{{yaml git}}}", &data).unwrap(),
+            "email: me@example.com"
+        );
+    }
+
+    #[test]
+    fn read_file_helper() {
+        let path = env::temp_dir().join("nk-test-helpers-read-file.txt");
+        std::fs::write(&path, "contents\n").unwrap();
+        let data = json!({"path": path.to_string_lossy()});
+
+        assert_eq!(render("{{read_file path}}", &data).unwrap(), "contents\n");
+        assert!(render(
+            r#"{{read_file "/nk-test/file-that-does-not-exist"}}"#,
+            &data
+        )
+        .is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn command_output_helper() {
+        assert_eq!(
+            render(r#"{{command_output "echo" "a" "b"}}"#, &json!({})).unwrap(),
+            "a b"
+        );
+
+        let error =
+            render(r#"{{command_output "sh" "-c" "exit 3"}}"#, &json!({}))
+                .unwrap_err();
+        assert!(error.to_string().contains("sh -c exit 3"));
+    }
+
+    #[test]
+    fn command_output_is_cached() {
+        let path = env::temp_dir().join("nk-test-helpers-command-count");
+        std::fs::write(&path, "").unwrap();
+        let data = json!({"path": path.to_string_lossy()});
+        let template =
+            r#"{{command_output "sh" "-c" "echo x >> \"$0\"" path}}"#;
+
+        render(template, &data).unwrap();
+        render(template, &data).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "x\n");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn if_var_helpers() {
+        let data = json!({"os": "linux", "roles": ["work", "dev"]});
+
+        assert_eq!(
+            render(r#"{{#if_os "macos" "linux"}}yes{{/if_os}}"#, &data)
+                .unwrap(),
+            "yes"
+        );
+        assert_eq!(
+            render(r#"{{#if_os "macos"}}yes{{else}}no{{/if_os}}"#, &data)
+                .unwrap(),
+            "no"
+        );
+        assert_eq!(
+            render(r#"{{#if_role "dev"}}yes{{else}}no{{/if_role}}"#, &data)
+                .unwrap(),
+            "yes"
+        );
+        assert_eq!(
+            render(r#"{{#if_role "ci"}}yes{{else}}no{{/if_role}}"#, &data)
+                .unwrap(),
+            "no"
+        );
+    }
+}
@@ -0,0 +1,5 @@
+mod helpers;
+mod templating;
+
+pub use self::helpers::*;
+pub use self::templating::*;
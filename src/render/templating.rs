@@ -0,0 +1,333 @@
+use handlebars::{Handlebars, RenderError};
+use rhai::{serde::from_dynamic, Scope};
+use serde_yml::{value::TaggedValue, Mapping, Value};
+use std::{collections::HashMap, path::PathBuf};
+
+use super::register_helpers;
+use crate::{
+    eval::Evaluator,
+    secrets::Secrets,
+    state::{Declaration, ResolvedGroup},
+};
+
+struct TemplatingEngine<'reg> {
+    registry: Handlebars<'reg>,
+    data: Mapping,
+    evaluator: &'reg Evaluator,
+    secrets: &'reg Secrets,
+    expression_paths: HashMap<String, Vec<PathBuf>>,
+}
+
+impl<'reg> TemplatingEngine<'reg> {
+    fn new(
+        data: Mapping,
+        evaluator: &'reg Evaluator,
+        secrets: &'reg Secrets,
+        expression_paths: HashMap<String, Vec<PathBuf>>,
+    ) -> Self {
+        let mut registry = Handlebars::new();
+        registry.set_strict_mode(true);
+        register_helpers(&mut registry);
+
+        Self {
+            registry,
+            data,
+            evaluator,
+            secrets,
+            expression_paths,
+        }
+    }
+
+    fn render(&self, template: &str) -> Result<String, RenderError> {
+        self.registry.render_template(template, &self.data)
+    }
+
+    // NOTE: !expr values are evaluated with the (current) vars in scope
+    fn eval(
+        &self,
+        expression: &str,
+    ) -> Result<Value, Box<dyn std::error::Error>> {
+        let result = self.evaluator.with_vars(self.data.clone(), || {
+            self.evaluator
+                .eval_expression(expression, &mut Scope::new())
+        });
+
+        result.and_then(|r| Ok(from_dynamic(&r)?)).map_err(|e| {
+            let paths = self.expression_paths.get(expression).map_or_else(
+                String::new,
+                |paths| {
+                    format!(
+                        ": in {}",
+                        paths
+                            .iter()
+                            .map(|p| p.display().to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                },
+            );
+            format!("{e}: in !expr: {expression}{paths}").into()
+        })
+    }
+
+    // NOTE: secrets are only read when rendered (ie. not for groups that
+    // don't match), and are redacted from anything nk prints
+    fn secret(&self, name: &str) -> Result<Value, Box<dyn std::error::Error>> {
+        self.secrets
+            .get(name)
+            .map(Value::String)
+            .map_err(|e| format!("{e}: in !secret: {name}").into())
+    }
+}
+
+pub fn render_group(
+    group: ResolvedGroup,
+    evaluator: &Evaluator,
+    secrets: &Secrets,
+) -> Result<ResolvedGroup, Box<dyn std::error::Error>> {
+    let mut engine = TemplatingEngine::new(
+        group.vars,
+        evaluator,
+        secrets,
+        group.expression_paths,
+    );
+    render_vars(&mut engine)?;
+
+    let declarations = group
+        .declarations
+        .into_iter()
+        .map(|(k, d)| Ok((k, render_declaration(&engine, d)?)))
+        .collect::<Result<_, Box<dyn std::error::Error>>>()?;
+
+    Ok(ResolvedGroup {
+        vars: engine.data,
+        declarations,
+        expression_paths: engine.expression_paths,
+    })
+}
+
+// NOTE: vars may reference other (templated) vars, so they're rendered in
+// passes (each rendering the original vars using the vars rendered by the
+// previous pass) until nothing changes
+fn render_vars(
+    engine: &mut TemplatingEngine,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let vars = engine.data.clone();
+    engine.data = strip_raw_tags_from_mapping(vars.clone());
+
+    let render_pass = |engine: &TemplatingEngine| {
+        vars.iter()
+            .map(|(k, v)| {
+                let var = render_state(engine, v.clone()).map_err(|e| {
+                    format!(
+                        "{e}: while rendering var: {}",
+                        k.as_str().unwrap_or_default()
+                    )
+                })?;
+                Ok((k.clone(), var))
+            })
+            .collect::<Result<Mapping, Box<dyn std::error::Error>>>()
+    };
+
+    // NOTE: a chain of n vars referencing each other takes at most n passes
+    for _ in 0..=vars.len() {
+        let rendered = render_pass(engine)?;
+        let data = strip_raw_tags_from_mapping(rendered.clone());
+
+        if data == engine.data {
+            // NOTE: vars which still contain templates may reference
+            // themselves (ie. a: "{{a}}"), which is the case if they can't be
+            // rendered without themselves
+            let mut cyclic_vars = vec![];
+            for (k, v) in &vars {
+                if !rendered.get(k).is_some_and(contains_template) {
+                    continue;
+                }
+
+                let data = engine.data.clone();
+                engine.data.remove(k);
+                if render_state(engine, v.clone()).is_err() {
+                    cyclic_vars.push(k.as_str().unwrap_or_default());
+                }
+                engine.data = data;
+            }
+
+            return if cyclic_vars.is_empty() {
+                Ok(())
+            } else {
+                Err(cycle_error(&cyclic_vars))
+            };
+        }
+
+        engine.data = data;
+    }
+
+    // still changing, so some vars must (indirectly) reference themselves
+    let data = strip_raw_tags_from_mapping(render_pass(engine)?);
+    let changing_vars = data
+        .iter()
+        .filter(|(k, v)| engine.data.get(k) != Some(v))
+        .filter_map(|(k, _)| k.as_str())
+        .collect::<Vec<_>>();
+
+    Err(cycle_error(&changing_vars))
+}
+
+fn cycle_error(vars: &[&str]) -> Box<dyn std::error::Error> {
+    format!("cycle detected while rendering vars: {}", vars.join(", ")).into()
+}
+
+// NOTE: !raw values are not checked since they're never rendered
+fn contains_template(value: &Value) -> bool {
+    match value {
+        Value::String(v) => v.contains("{{"),
+        Value::Sequence(v) => v.iter().any(contains_template),
+        Value::Mapping(v) => v
+            .iter()
+            .any(|(k, v)| contains_template(k) || contains_template(v)),
+        Value::Tagged(v) => !is_raw(v) && contains_template(&v.value),
+        _ => false,
+    }
+}
+
+fn is_raw(tagged: &TaggedValue) -> bool {
+    tagged.tag == "raw"
+}
+
+fn is_expr(tagged: &TaggedValue) -> bool {
+    tagged.tag == "expr"
+}
+
+fn is_secret(tagged: &TaggedValue) -> bool {
+    tagged.tag == "secret"
+}
+
+// NOTE: removes the !raw tags (after rendering), preserving their values as is
+fn strip_raw_tags(value: Value) -> Value {
+    match value {
+        Value::Tagged(v) if is_raw(&v) => strip_raw_tags(v.value),
+        Value::Tagged(v) => Value::Tagged(Box::new(TaggedValue {
+            tag: v.tag,
+            value: strip_raw_tags(v.value),
+        })),
+        Value::Sequence(v) => v.into_iter().map(strip_raw_tags).collect(),
+        Value::Mapping(v) => Value::Mapping(strip_raw_tags_from_mapping(v)),
+        v => v,
+    }
+}
+
+fn strip_raw_tags_from_mapping(mapping: Mapping) -> Mapping {
+    mapping
+        .into_iter()
+        .map(|(k, v)| (strip_raw_tags(k), strip_raw_tags(v)))
+        .collect()
+}
+
+fn render_declaration(
+    engine: &TemplatingEngine,
+    declaration: Declaration,
+) -> Result<Declaration, Box<dyn std::error::Error>> {
+    let states = declaration
+        .states
+        .into_iter()
+        .map(|s| render_state(engine, s).map(strip_raw_tags))
+        .collect::<Result<_, _>>()?;
+
+    Ok(Declaration {
+        name: declaration.name,
+        states,
+    })
+}
+
+fn render_state(
+    engine: &TemplatingEngine,
+    state: Value,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    match state {
+        Value::String(v) => Ok(Value::String(engine.render(&v)?)),
+        Value::Sequence(v) => Ok(v
+            .into_iter()
+            .map(|s| render_state(engine, s))
+            .collect::<Result<Value, _>>()?),
+        Value::Mapping(v) => Ok(Value::Mapping(
+            v.into_iter()
+                .map(|(k, s)| {
+                    Ok((render_state(engine, k)?, render_state(engine, s)?))
+                })
+                .collect::<Result<_, Box<dyn std::error::Error>>>()?,
+        )),
+        // NOTE: !raw values are left as is
+        Value::Tagged(v) if is_raw(&v) => Ok(Value::Tagged(v)),
+        Value::Tagged(v) if is_expr(&v) => match &v.value {
+            Value::String(expression) => engine.eval(expression),
+            _ => Err("!expr must be a string".into()),
+        },
+        Value::Tagged(v) if is_secret(&v) => match &v.value {
+            Value::String(name) => engine.secret(name),
+            _ => Err("!secret must be a string".into()),
+        },
+        Value::Tagged(v) => Ok(Value::Tagged(Box::new(TaggedValue {
+            tag: v.tag,
+            value: render_state(engine, v.value)?,
+        }))),
+        v => Ok(v),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml<T: serde::de::DeserializeOwned>(contents: &str) -> T {
+        serde_yml::from_str(contents).unwrap()
+    }
+
+    fn rendered_vars(
+        vars: &str,
+    ) -> Result<Mapping, Box<dyn std::error::Error>> {
+        let evaluator = Evaluator::new(Mapping::new());
+        let secrets = Secrets::new(None);
+        let mut engine = TemplatingEngine::new(
+            yaml(vars),
+            &evaluator,
+            &secrets,
+            HashMap::new(),
+        );
+
+        render_vars(&mut engine)?;
+
+        Ok(engine.data)
+    }
+
+    #[test]
+    fn render_chain_of_vars() {
+        let vars = rendered_vars(
+            r#"{c: "{{b}}/c", b: "{{a}}/b", a: /a, raw: !raw "{{a}}"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            vars,
+            yaml::<Mapping>(r#"{c: /a/b/c, b: /a/b, a: /a, raw: "{{a}}"}"#)
+        );
+    }
+
+    #[test]
+    fn render_self_referencing_var() {
+        let error = rendered_vars(r#"{a: "{{a}}", b: ok}"#).unwrap_err();
+
+        assert_eq!(error.to_string(), "cycle detected while rendering vars: a");
+    }
+
+    #[test]
+    fn render_indirectly_cyclic_vars() {
+        let error =
+            rendered_vars(r#"{a: "{{b}}", b: "{{a}}", c: "{{d}}", d: ok}"#)
+                .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "cycle detected while rendering vars: a, b"
+        );
+    }
+}
@@ -1,12 +1,17 @@
 use crate::{
     config::Config,
     eval::Evaluator,
-    merge::{merge_groups, merge_plugin_dependencies},
-    plugins::Plugin,
+    merge::{merge_groups, merge_plugin_dependencies, merge_vars},
+    plugins::{load_plugins, Plugin, ProvisionInfo},
     render::render_group,
+    secrets::{redact, Secrets},
     state::{self, ResolvedGroup},
+    vars::get_global_var_origins,
 };
-use serde_yml::Mapping;
+use itertools::Itertools;
+use jsonschema::Validator;
+use serde_yml::{Mapping, Value};
+use std::collections::HashMap;
 
 pub struct ResolveOptions {
     pub render: bool,
@@ -20,10 +25,22 @@ pub fn resolve(
     options: &ResolveOptions,
 ) -> Result<ResolvedGroup, Box<dyn std::error::Error>> {
     // find all state files for this machine
-    let files = state::File::find_all(&config.sources)?;
+    let files = state::File::find_all(&config.sources, config.verbose)?;
+
+    // merge in vars from vars plugins (before filtering, so conditions can use them)
+    // NOTE: where vars were (last) set by plugins/groups, for validation errors
+    let mut origins = HashMap::new();
+    let global_vars =
+        &merge_plugin_vars(config, global_vars.clone(), plugins, &mut origins)?;
+    evaluator.set_vars(global_vars.clone());
 
     // filter groups based on conditions
     let groups = evaluator.filter_files_to_matching_groups(&files)?;
+    for group in &groups {
+        for name in group.vars.keys().filter_map(Value::as_str) {
+            origins.insert(name.to_string(), group.path.display().to_string());
+        }
+    }
 
     // merge in plugin dependencies
     let resolved = plugins
@@ -39,8 +56,111 @@ pub fn resolve(
 
     // render resolved
     if options.render {
-        Ok(render_group(resolved)?)
+        let secrets = Secrets::new(config.secrets.clone());
+        let resolved = render_group(resolved, evaluator, &secrets)?;
+
+        // NOTE: only rendered vars are validated (templates are strings until rendered)
+        if let Some(schema) = &config.vars_schema {
+            validate_vars(config, schema, &resolved.vars, &origins)?;
+        }
+
+        Ok(resolved)
     } else {
         Ok(resolved)
     }
 }
+
+fn validate_vars(
+    config: &Config,
+    schema: &Value,
+    vars: &Mapping,
+    origins: &HashMap<String, String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let json_schema = serde_json::to_value(schema)?;
+    let validator = Validator::new(&json_schema)
+        .map_err(|e| format!("error parsing vars_schema: {e}"))?;
+
+    let json_vars = serde_json::to_value(vars)?;
+    let errors = validator.iter_errors(&json_vars).collect::<Vec<_>>();
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    // NOTE: global var origins are only looked up when needed
+    let global_origins = get_global_var_origins(&config.sources)?;
+    let messages = errors
+        .iter()
+        .map(|error| {
+            // NOTE: the var is the first segment of the json pointer (ie. /roles/0)
+            let pointer = error.instance_path.as_str();
+            let name = pointer
+                .trim_start_matches('/')
+                .split('/')
+                .next()
+                .unwrap_or_default()
+                .replace("~1", "/")
+                .replace("~0", "~");
+            if name.is_empty() {
+                return redact(&error.to_string());
+            }
+
+            let origin = origins
+                .get(&name)
+                .or_else(|| global_origins.get(&name))
+                .map_or_else(String::new, |o| format!(" (set in {o})"));
+            redact(&format!("{pointer}: {error}{origin}"))
+        })
+        .collect::<Vec<_>>();
+
+    Err(format!(
+        "invalid vars (according to vars_schema):\n\t- {}",
+        messages.join("\n\t- ")
+    )
+    .into())
+}
+
+// NOTE: for commands that don't otherwise load plugins (ie. eval/var), so
+// they see the same vars as groups do when provisioning
+pub async fn get_vars_with_plugins(
+    config: &Config,
+    global_vars: Mapping,
+    origins: &mut HashMap<String, String>,
+) -> Result<Mapping, Box<dyn std::error::Error>> {
+    let evaluator = Evaluator::new(global_vars.clone());
+    let plugins = load_plugins(config, &evaluator).await?;
+
+    merge_plugin_vars(config, global_vars, &plugins, origins)
+}
+
+// NOTE: vars plugins are run in a deterministic order (config order, then by
+// name), with each receiving the vars merged so far
+fn merge_plugin_vars(
+    config: &Config,
+    mut vars: Mapping,
+    plugins: &[Plugin],
+    origins: &mut HashMap<String, String>,
+) -> Result<Mapping, Box<dyn std::error::Error>> {
+    for plugin in plugins
+        .iter()
+        .filter(|p| p.definition.vars)
+        .sorted_by_key(|p| (p.config_index, &p.definition.name))
+    {
+        let info = ProvisionInfo {
+            sources: config.sources.clone(),
+            vars: vars.clone(),
+        };
+        let plugin_vars = plugin.vars(&info).map_err(|e| {
+            format!("{e}: in vars plugin: {}", plugin.definition.name)
+        })?;
+
+        for name in plugin_vars.keys().filter_map(Value::as_str) {
+            origins.insert(
+                name.to_string(),
+                format!("vars plugin: {}", plugin.definition.name),
+            );
+        }
+        merge_vars(&mut vars, plugin_vars);
+    }
+
+    Ok(vars)
+}
@@ -0,0 +1,285 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use serde_yml::{Mapping, Value};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    process::Command,
+    str::FromStr,
+    sync::{Mutex, RwLock},
+};
+
+// NOTE: where `!secret name` values are read from, either:
+// - command: run with `sh -c`, with the secret's name as $1 (ie. `pass show nk/$1`)
+// - file: an encrypted yaml mapping of secret names to values, decrypted with
+//   gpg (.gpg/.asc) or age (.age, using the identity file)
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SecretsConfig {
+    pub command: Option<String>,
+    pub file: Option<String>,
+    pub identity: Option<String>,
+}
+
+// NOTE: secrets are redacted by searching for their values in any output, so
+// short values (ie. `dev` or `true`) would redact unrelated text
+const MIN_SECRET_LENGTH: usize = 8;
+
+lazy_static! {
+    static ref SECRET_NAME_REGEX: Regex =
+        Regex::new("^[A-Za-z0-9_./-]+$").unwrap();
+    // NOTE: every secret value read during this run, so they can be redacted
+    // from anything nk prints
+    static ref SECRET_VALUES: RwLock<Vec<String>> = RwLock::new(vec![]);
+}
+
+pub struct Secrets {
+    config: Option<SecretsConfig>,
+    cache: Mutex<HashMap<String, String>>,
+    file: Mutex<Option<Mapping>>,
+}
+
+impl Secrets {
+    pub fn new(config: Option<SecretsConfig>) -> Self {
+        Self {
+            config,
+            cache: Mutex::new(HashMap::new()),
+            file: Mutex::new(None),
+        }
+    }
+
+    pub fn get(
+        &self,
+        name: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        if let Some(value) =
+            self.cache.lock().expect("secrets cache lock").get(name)
+        {
+            return Ok(value.clone());
+        }
+
+        if !SECRET_NAME_REGEX.is_match(name) {
+            return Err(format!("invalid secret name: {name}").into());
+        }
+
+        let value = match &self.config {
+            Some(SecretsConfig {
+                command: Some(command),
+                file: None,
+                ..
+            }) => read_command_secret(command, name)?,
+            Some(SecretsConfig {
+                command: None,
+                file: Some(file),
+                identity,
+            }) => self.read_file_secret(file, identity.as_deref(), name)?,
+            Some(_) => {
+                return Err("secrets: should have either command or file".into())
+            }
+            None => {
+                return Err(format!(
+                    "secret {name} requires secrets to be configured in .nk.yml"
+                )
+                .into())
+            }
+        };
+
+        if value.chars().count() < MIN_SECRET_LENGTH {
+            return Err(format!(
+                "secret {name} is too short to be redacted safely (should be at least {MIN_SECRET_LENGTH} characters)"
+            )
+            .into());
+        }
+
+        register_secret(&value);
+        self.cache
+            .lock()
+            .expect("secrets cache lock")
+            .insert(name.to_string(), value.clone());
+
+        Ok(value)
+    }
+
+    fn read_file_secret(
+        &self,
+        file: &str,
+        identity: Option<&str>,
+        name: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let mut decrypted = self.file.lock().expect("secrets file lock");
+        if decrypted.is_none() {
+            *decrypted = Some(decrypt_file(file, identity)?);
+        }
+        let value = decrypted.as_ref().and_then(|s| s.get(name)).cloned();
+        drop(decrypted);
+
+        match value {
+            Some(Value::String(value)) => Ok(value),
+            Some(value) => Ok(serde_yml::to_string(&value)?.trim_end().into()),
+            None => Err(format!("secret not found: {name}: in {file}").into()),
+        }
+    }
+}
+
+fn read_command_secret(
+    command: &str,
+    name: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new("sh")
+        .args(["-c", command, "nk", name])
+        .output()
+        .map_err(|e| format!("{e}: {command}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "secret command failed with {}: {command}",
+            output.status
+        )
+        .into());
+    }
+
+    Ok(String::from_utf8(output.stdout)?.trim_end().to_string())
+}
+
+fn decrypt_file(
+    file: &str,
+    identity: Option<&str>,
+) -> Result<Mapping, Box<dyn std::error::Error>> {
+    let path = PathBuf::from_str(&shellexpand::tilde(file))?;
+
+    let mut command = match path.extension().and_then(|e| e.to_str()) {
+        Some("age") => {
+            let mut command = Command::new("age");
+            command.arg("--decrypt");
+            if let Some(identity) = identity {
+                command.args(["--identity", &shellexpand::tilde(identity)]);
+            }
+            command
+        }
+        Some("gpg" | "asc") => {
+            let mut command = Command::new("gpg");
+            command.args(["--quiet", "--batch", "--decrypt"]);
+            command
+        }
+        _ => {
+            return Err(format!(
+            "unsupported secrets file (should be .age, .gpg or .asc): {file}"
+        )
+            .into())
+        }
+    };
+
+    let output = command
+        .arg(&path)
+        .output()
+        .map_err(|e| format!("{e}: while decrypting {file}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "decrypting failed with {}: {file}\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        )
+        .into());
+    }
+
+    let secrets: Option<Mapping> = serde_yml::from_slice(&output.stdout)
+        .map_err(|e| {
+            format!("{e}: secrets file should be a mapping: {file}")
+        })?;
+
+    Ok(secrets.unwrap_or_default())
+}
+
+fn register_secret(value: &str) {
+    if value.is_empty() {
+        return;
+    }
+
+    let mut values = SECRET_VALUES.write().expect("secret values lock");
+    // NOTE: as it would appear in json (ie. with escaped quotes/newlines)
+    let escaped = serde_json::to_string(value)
+        .map(|v| v[1..v.len() - 1].to_string())
+        .unwrap_or_default();
+    for v in [value.to_string(), escaped] {
+        if !v.is_empty() && !values.contains(&v) {
+            values.push(v);
+        }
+    }
+    // NOTE: longest first, so secrets containing other secrets are fully redacted
+    values.sort_by_key(|v| std::cmp::Reverse(v.len()));
+}
+
+pub fn redact(text: &str) -> String {
+    SECRET_VALUES
+        .read()
+        .expect("secret values lock")
+        .iter()
+        .fold(text.to_string(), |text, secret| {
+            text.replace(secret, "[redacted]")
+        })
+}
+
+pub fn contains_secret(text: &str) -> bool {
+    SECRET_VALUES
+        .read()
+        .expect("secret values lock")
+        .iter()
+        .any(|secret| text.contains(secret))
+}
+
+pub fn redact_value(value: &Value) -> Value {
+    match value {
+        Value::String(v) => Value::String(redact(v)),
+        Value::Sequence(v) => v.iter().map(redact_value).collect(),
+        Value::Mapping(v) => Value::Mapping(
+            v.iter()
+                .map(|(k, v)| (k.clone(), redact_value(v)))
+                .collect(),
+        ),
+        v => v.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command_secrets(command: &str) -> Secrets {
+        Secrets::new(Some(SecretsConfig {
+            command: Some(command.to_string()),
+            file: None,
+            identity: None,
+        }))
+    }
+
+    #[test]
+    fn short_secrets_are_refused() {
+        let secrets = command_secrets("echo dev");
+
+        let error = secrets.get("short").unwrap_err();
+
+        assert!(error.to_string().contains("too short"));
+        assert!(!contains_secret("dev"));
+    }
+
+    #[test]
+    fn secrets_are_redacted() {
+        let secrets = command_secrets(r#"echo "nk-test-$1-value""#);
+
+        let value = secrets.get("redacted").unwrap();
+
+        assert_eq!(value, "nk-test-redacted-value");
+        assert!(contains_secret("token: nk-test-redacted-value"));
+        assert_eq!(
+            redact("token: nk-test-redacted-value, cpus: 1"),
+            "token: [redacted], cpus: 1"
+        );
+    }
+
+    #[test]
+    fn invalid_secret_names_are_refused() {
+        let secrets = command_secrets("echo nk-test-value");
+
+        assert!(secrets.get("$(id)").is_err());
+    }
+}
@@ -1,8 +1,99 @@
-use serde::{Deserialize, Serialize};
+use lazy_static::lazy_static;
+use rhai::{Engine, AST};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use serde_with::{DeserializeAs, OneOrMany, Same};
+use std::{
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
 
-#[derive(Serialize, Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
-#[serde(deny_unknown_fields, transparent)]
+lazy_static! {
+    // NOTE: only used for parsing, conditions are evaluated by eval::Evaluator
+    static ref PARSER: Engine = Engine::new_raw();
+}
+
+#[derive(Clone)]
 pub struct Condition {
-    // TODO: decide if this is how we'll store this... maybe could parse and store parsed
     pub rule: String,
+    pub ast: Arc<AST>,
+}
+
+impl Condition {
+    pub fn parse(rule: String) -> Result<Self, String> {
+        let ast = PARSER
+            .compile_expression(&rule)
+            .map_err(|e| format!("{e}: in condition: {rule}"))?;
+
+        Ok(Self {
+            rule,
+            ast: Arc::new(ast),
+        })
+    }
+}
+
+impl std::fmt::Debug for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Condition")
+            .field("rule", &self.rule)
+            .finish()
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.rule)
+    }
+}
+
+// NOTE: custom implementations because the AST is derived from the rule
+impl PartialEq for Condition {
+    fn eq(&self, other: &Self) -> bool {
+        self.rule == other.rule
+    }
+}
+impl Eq for Condition {}
+impl Hash for Condition {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        self.rule.hash(hasher);
+    }
+}
+
+impl Serialize for Condition {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.rule.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Condition {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let rule: String = Deserialize::deserialize(deserializer)?;
+
+        Self::parse(rule).map_err(D::Error::custom)
+    }
+}
+
+// NOTE: equivalent to OneOrMany<_>, but reports syntax errors directly
+// (instead of as a failure to match any variant)
+pub struct Conditions;
+
+impl<'de> DeserializeAs<'de, Vec<Condition>> for Conditions {
+    fn deserialize_as<D>(deserializer: D) -> Result<Vec<Condition>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let rules: Vec<String> =
+            OneOrMany::<Same>::deserialize_as(deserializer)?;
+
+        rules
+            .into_iter()
+            .map(Condition::parse)
+            .collect::<Result<_, _>>()
+            .map_err(D::Error::custom)
+    }
 }
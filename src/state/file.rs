@@ -1,6 +1,12 @@
 use super::Group;
+use console::style;
 use serde::Deserialize;
-use std::{ffi::OsStr, path::PathBuf};
+use std::{
+    collections::HashSet,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 #[derive(Debug)]
 pub struct File {
@@ -8,21 +14,81 @@ pub struct File {
     pub groups: Vec<Group>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl FileFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("yml" | "yaml") => Some(Self::Yaml),
+            Some("json") => Some(Self::Json),
+            Some("toml") => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    // NOTE: json/toml files are only discovered in directories when they have
+    // a `.nk` suffix (ie. `packages.nk.json`), so unrelated files (ie.
+    // renovate.json or Cargo.toml) aren't treated as state (they can still be
+    // included explicitly by path)
+    fn is_discoverable(path: &Path) -> bool {
+        match Self::from_path(path) {
+            Some(Self::Yaml) => true,
+            Some(Self::Json | Self::Toml) => path
+                .file_stem()
+                .and_then(OsStr::to_str)
+                .is_some_and(|stem| stem.ends_with(".nk")),
+            None => false,
+        }
+    }
+}
+
 impl File {
     pub fn from_path(
         path: PathBuf,
     ) -> Result<File, Box<dyn std::error::Error>> {
+        let format = FileFormat::from_path(&path).ok_or_else(|| {
+            format!(
+                "unsupported state file extension (expected yml, yaml, json, or toml): {}",
+                path.display()
+            )
+        })?;
         let contents = std::fs::read_to_string(&path)?;
 
-        let groups = serde_yml::Deserializer::from_str(&contents)
-            .map(Group::deserialize)
-            .collect::<Result<Vec<_>, _>>()?;
+        // NOTE: groups are numbered from 1 (in document order) in errors
+        let group_error = |index: usize, e: &dyn std::fmt::Display| {
+            format!("{}: {} (group {})", e, path.display(), index + 1)
+        };
+        let mut groups = match format {
+            FileFormat::Yaml => serde_yml::Deserializer::from_str(&contents)
+                .map(Group::deserialize)
+                .enumerate()
+                .map(|(i, g)| g.map_err(|e| group_error(i, &e)))
+                .collect::<Result<Vec<_>, _>>()?,
+            // NOTE: multiple groups can be provided as a stream of json values
+            FileFormat::Json => serde_json::Deserializer::from_str(&contents)
+                .into_iter::<Group>()
+                .enumerate()
+                .map(|(i, g)| g.map_err(|e| group_error(i, &e)))
+                .collect::<Result<Vec<_>, _>>()?,
+            // NOTE: toml has no concept of multiple documents
+            FileFormat::Toml => vec![toml::from_str::<Group>(&contents)
+                .map_err(|e| format!("{}: {}", e, path.display()))?],
+        };
+        for group in &mut groups {
+            group.path.clone_from(&path);
+        }
 
         Ok(File { path, groups })
     }
 
     fn find_all_in_dir(
         directory: &PathBuf,
+        verbose: bool,
     ) -> Result<Vec<File>, Box<dyn std::error::Error>> {
         let mut source_files: Vec<File> = vec![];
 
@@ -36,20 +102,31 @@ impl File {
             let dir_entry = res?;
             let metadata = dir_entry.metadata()?;
             let path = dir_entry.path();
-            let extension = path.extension().unwrap_or_default();
             let lossy_file_stem = path
                 .file_stem()
                 .map(OsStr::to_string_lossy)
                 .unwrap_or_default();
 
             if metadata.is_file()
-                && extension == "yml"
+                && FileFormat::is_discoverable(&path)
                 && !lossy_file_stem.starts_with('.')
             {
                 source_files.push(File::from_path(path)?);
-            } else {
-                // TODO: likely ignore, but log (debug level)
-                // println!("ignoring: {}", path.display());
+            } else if metadata.is_file()
+                && FileFormat::from_path(&path).is_some()
+                && !lossy_file_stem.starts_with('.')
+            {
+                // NOTE: always warned about, since it's likely meant as state
+                eprintln!(
+                    "{}",
+                    style(format!(
+                        "ignoring: {} (json/toml state files should be named *.nk.json/*.nk.toml)",
+                        path.display()
+                    ))
+                    .yellow()
+                );
+            } else if verbose {
+                eprintln!("ignoring: {}", path.display());
             }
         }
 
@@ -60,16 +137,303 @@ impl File {
         Ok(source_files)
     }
 
+    // NOTE: files included by another file (ie. a gated `include:` of a file
+    // in the same source) are only applied through that include, rather than
+    // also being discovered at the top level
     pub fn find_all(
         sources: &[PathBuf],
+        verbose: bool,
     ) -> Result<Vec<File>, Box<dyn std::error::Error>> {
-        let mut files: Vec<File> = vec![];
+        let mut included = HashSet::new();
+        let mut expanded = vec![];
 
         // top level
         for source in sources {
-            files.append(&mut File::find_all_in_dir(source)?);
+            for file in File::find_all_in_dir(source, verbose)? {
+                let canonical_path = canonicalize(&file.path)?;
+                expanded.push((
+                    canonical_path,
+                    file.expand_includes(&[], &mut included, verbose)?,
+                ));
+            }
+        }
+
+        let mut files: Vec<File> = vec![];
+        for (canonical_path, mut expanded_files) in expanded {
+            if included.contains(&canonical_path) {
+                if verbose {
+                    eprintln!(
+                        "ignoring (included by another file): {}",
+                        canonical_path.display()
+                    );
+                }
+                continue;
+            }
+            files.append(&mut expanded_files);
         }
 
         Ok(files)
     }
+
+    // NOTE: included groups are placed immediately before the including group
+    // (so it can override their vars) and inherit its `when:` conditions
+    fn expand_includes(
+        self,
+        parents: &[PathBuf],
+        included: &mut HashSet<PathBuf>,
+        verbose: bool,
+    ) -> Result<Vec<File>, Box<dyn std::error::Error>> {
+        let canonical_path = canonicalize(&self.path)?;
+
+        if parents.contains(&canonical_path) {
+            return Err(format!(
+                "include cycle detected: {}",
+                parents
+                    .iter()
+                    .chain([&canonical_path])
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            )
+            .into());
+        }
+        if !parents.is_empty() {
+            included.insert(canonical_path.clone());
+        }
+
+        let mut stack = parents.to_vec();
+        stack.push(canonical_path);
+
+        let base_dir = self.path.parent().unwrap_or_else(|| Path::new("."));
+
+        let mut files = vec![];
+        let mut groups = vec![];
+        for mut group in self.groups {
+            let includes = std::mem::take(&mut group.include);
+
+            // NOTE: groups preceding the include are kept in their own file
+            if !includes.is_empty() && !groups.is_empty() {
+                files.push(File {
+                    path: self.path.clone(),
+                    groups: std::mem::take(&mut groups),
+                });
+            }
+
+            for include in includes {
+                let include_path = base_dir.join(PathBuf::from_str(
+                    &shellexpand::tilde(&include.to_string_lossy()),
+                )?);
+
+                let included_files = if include_path.is_dir() {
+                    File::find_all_in_dir(&include_path, verbose)?
+                } else {
+                    vec![File::from_path(include_path.clone()).map_err(
+                        |e| {
+                            format!(
+                                "{}: while including {} from {}",
+                                e,
+                                include_path.display(),
+                                self.path.display()
+                            )
+                        },
+                    )?]
+                };
+
+                for included_file in included_files {
+                    for mut expanded in included_file
+                        .expand_includes(&stack, included, verbose)?
+                    {
+                        // included groups only apply when the including group does
+                        for included_group in &mut expanded.groups {
+                            included_group.when = group
+                                .when
+                                .iter()
+                                .cloned()
+                                .chain(included_group.when.drain(..))
+                                .collect();
+                        }
+                        files.push(expanded);
+                    }
+                }
+            }
+
+            groups.push(group);
+        }
+
+        if !groups.is_empty() {
+            files.push(File {
+                path: self.path,
+                groups,
+            });
+        }
+
+        Ok(files)
+    }
+}
+
+fn canonicalize(path: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(path
+        .canonicalize()
+        .map_err(|e| format!("{}: {}", e, path.display()))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_errors_include_the_group_index() {
+        let path = std::env::temp_dir().join("nk-test-file-group-error.yml");
+        std::fs::write(&path, "packages: [git]\n---\nwhen: 'os =='\n").unwrap();
+
+        let error = File::from_path(path.clone()).unwrap_err();
+
+        assert!(error
+            .to_string()
+            .ends_with(&format!("{} (group 2)", path.display())));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn discoverable_state_files() {
+        for path in ["a.yml", "a.yaml", "a.nk.json", "a.nk.toml"] {
+            assert!(FileFormat::is_discoverable(Path::new(path)), "{path}");
+        }
+        for path in ["renovate.json", "Cargo.toml", "nk.json", "readme.txt"] {
+            assert!(!FileFormat::is_discoverable(Path::new(path)), "{path}");
+        }
+    }
+
+    // NOTE: a fresh source directory with the given files (and directories)
+    fn source(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let source = std::env::temp_dir().join(format!("nk-test-{name}"));
+        let _ = std::fs::remove_dir_all(&source);
+        for (path, contents) in files {
+            let path = source.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        source
+    }
+
+    // NOTE: each group as its packages and conditions, in order
+    fn groups(files: &[File]) -> Vec<(String, Vec<String>)> {
+        files
+            .iter()
+            .flat_map(|f| &f.groups)
+            .map(|g| {
+                (
+                    g.declarations
+                        .keys()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    g.when.iter().map(|c| c.rule.clone()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn includes_are_expanded_before_the_including_group() {
+        let source = source(
+            "include-expansion",
+            &[
+                (
+                    "a.yml",
+                    "packages: [git]\n---\ninclude: ./lib/b.yml\nfiles: []\n",
+                ),
+                ("lib/b.yml", "apt: []\n"),
+            ],
+        );
+
+        let files =
+            File::find_all(std::slice::from_ref(&source), false).unwrap();
+
+        assert_eq!(
+            groups(&files),
+            [
+                ("packages".into(), vec![]),
+                ("apt".into(), vec![]),
+                ("files".into(), vec![]),
+            ]
+        );
+        std::fs::remove_dir_all(source).unwrap();
+    }
+
+    #[test]
+    fn gated_includes_are_not_discovered_at_the_top_level() {
+        let source = source(
+            "include-gated",
+            &[
+                ("g.yml", "when: os == \"macos\"\ninclude: ./mac-only.yml\n"),
+                ("mac-only.yml", "when: arch == \"aarch64\"\nmas: []\n"),
+            ],
+        );
+
+        let files =
+            File::find_all(std::slice::from_ref(&source), false).unwrap();
+
+        assert_eq!(
+            groups(&files),
+            [
+                (
+                    "mas".into(),
+                    vec![
+                        "os == \"macos\"".into(),
+                        "arch == \"aarch64\"".into()
+                    ]
+                ),
+                (String::new(), vec!["os == \"macos\"".into()]),
+            ]
+        );
+        std::fs::remove_dir_all(source).unwrap();
+    }
+
+    #[test]
+    fn directory_includes_inherit_conditions() {
+        let source = source(
+            "include-directory",
+            &[
+                ("a.yml", "when: os == \"linux\"\ninclude: ./linux\n"),
+                ("linux/apt.yml", "apt: []\n"),
+                ("linux/flatpak.yml", "flatpak: []\n"),
+                ("linux/readme.md", "not state\n"),
+            ],
+        );
+
+        let files =
+            File::find_all(std::slice::from_ref(&source), false).unwrap();
+
+        assert_eq!(
+            groups(&files),
+            [
+                ("apt".into(), vec!["os == \"linux\"".into()]),
+                ("flatpak".into(), vec!["os == \"linux\"".into()]),
+                (String::new(), vec!["os == \"linux\"".into()]),
+            ]
+        );
+        std::fs::remove_dir_all(source).unwrap();
+    }
+
+    #[test]
+    fn include_cycles_are_detected() {
+        let source = source(
+            "include-cycle",
+            &[
+                ("a.yml", "include: ./lib/b.yml\n"),
+                ("lib/b.yml", "include: ../a.yml\n"),
+            ],
+        );
+
+        let error =
+            File::find_all(std::slice::from_ref(&source), false).unwrap_err();
+
+        assert!(
+            error.to_string().starts_with("include cycle detected: "),
+            "{error}"
+        );
+        assert!(error.to_string().ends_with("a.yml"), "{error}");
+        std::fs::remove_dir_all(source).unwrap();
+    }
 }
@@ -1,23 +1,143 @@
-use super::{Condition, Declaration, RawDeclaration};
-use crate::utils::deserialize_map_to_map_of_named;
+use super::{Condition, Conditions, Declaration, RawDeclaration};
+use crate::{
+    merge::{split_merge_strategy, MergeStrategy},
+    traits::IntoWithName,
+    utils::deserialize_map_to_map_of_named,
+    vars::VarSources,
+};
 use serde::{Deserialize, Serialize};
-use serde_with::{serde_as, OneOrMany};
-use serde_yml::Mapping;
-use std::collections::HashMap;
+use serde_with::{DeserializeAs, OneOrMany, Same};
+use serde_yml::{
+    value::{Tag, TaggedValue},
+    Mapping, Value,
+};
+use std::{collections::HashMap, path::PathBuf};
 
-#[serde_as]
+// NOTE: deserialized via a Mapping (instead of #[serde(flatten)]) so yaml tags
+// (ie. !raw) are preserved in states
 #[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "Mapping")]
 pub struct Group {
-    #[serde_as(deserialize_as = "OneOrMany<_>", serialize_as = "OneOrMany<_>")]
-    #[serde(default)]
     pub when: Vec<Condition>,
-    #[serde(default)]
     pub vars: Mapping,
-    #[serde(
-        flatten,
-        deserialize_with = "deserialize_map_to_map_of_named::<RawDeclaration, _, _>"
-    )]
+    pub var_sources: VarSources,
+    // NOTE: paths are relative to the including file
+    pub include: Vec<PathBuf>,
     pub declarations: HashMap<String, Declaration>,
+    // NOTE: from yaml tags on declarations (ie. `packages: !remove [docker]`)
+    pub declaration_strategies: HashMap<String, MergeStrategy>,
+    // NOTE: the file the group was declared in (set when the file is loaded)
+    pub path: PathBuf,
+}
+
+impl TryFrom<Mapping> for Group {
+    type Error = String;
+
+    fn try_from(mut mapping: Mapping) -> Result<Self, Self::Error> {
+        let when = mapping
+            .remove("when")
+            .map(Conditions::deserialize_as)
+            .transpose()
+            .map_err(|e| format!("when: {e}"))?
+            .unwrap_or_default();
+        let mut vars: Mapping = mapping
+            .remove("vars")
+            .map(|v| {
+                validate_tagged_values(&v)?;
+                serde_yml::from_value(v).map_err(|e| e.to_string())
+            })
+            .transpose()
+            .map_err(|e| format!("vars: {e}"))?
+            .unwrap_or_default();
+        // NOTE: equivalent to tagging the vars with the strategy
+        let merge: HashMap<String, MergeStrategy> = mapping
+            .remove("merge")
+            .map(serde_yml::from_value)
+            .transpose()
+            .map_err(|e| format!("merge: {e}"))?
+            .unwrap_or_default();
+        for (name, strategy) in merge {
+            let var = vars
+                .get_mut(name.as_str())
+                .ok_or_else(|| format!("merge: no var named: {name}"))?;
+            *var = Value::Tagged(Box::new(TaggedValue {
+                tag: Tag::new(strategy.tag()),
+                value: std::mem::take(var),
+            }));
+        }
+        let var_sources = VarSources {
+            from_env: mapping
+                .remove("from_env")
+                .map(serde_yml::from_value)
+                .transpose()
+                .map_err(|e| format!("from_env: {e}"))?
+                .unwrap_or_default(),
+            from_command: mapping
+                .remove("from_command")
+                .map(serde_yml::from_value)
+                .transpose()
+                .map_err(|e| format!("from_command: {e}"))?
+                .unwrap_or_default(),
+        };
+        let include =
+            match (mapping.remove("include"), mapping.remove("import")) {
+                (Some(_), Some(_)) => {
+                    Err("include: cannot be combined with import:".to_string())?
+                }
+                (Some(v), None) | (None, Some(v)) => {
+                    OneOrMany::<Same>::deserialize_as(v)
+                        .map_err(|e| format!("include: {e}"))?
+                }
+                (None, None) => vec![],
+            };
+
+        let mut declarations = HashMap::new();
+        let mut declaration_strategies = HashMap::new();
+        for (k, v) in mapping {
+            let name: String = serde_yml::from_value(k)
+                .map_err(|e| format!("declaration name: {e}"))?;
+            let (strategy, v) = split_merge_strategy(v);
+            validate_tagged_values(&v).map_err(|e| format!("{name}: {e}"))?;
+            let raw: RawDeclaration =
+                serde_yml::from_value(v).map_err(|e| format!("{name}: {e}"))?;
+
+            if let Some(strategy) = strategy {
+                declaration_strategies.insert(name.clone(), strategy);
+            }
+            declarations.insert(name.clone(), raw.into_with_name(name));
+        }
+
+        Ok(Self {
+            when,
+            vars,
+            var_sources,
+            include,
+            declarations,
+            declaration_strategies,
+            path: PathBuf::new(),
+        })
+    }
+}
+
+// NOTE: !expr/!secret values are evaluated while rendering, but checked
+// upfront so errors are reported with file context
+fn validate_tagged_values(value: &Value) -> Result<(), String> {
+    match value {
+        Value::Tagged(v) if v.tag == "expr" => match &v.value {
+            Value::String(expression) => Condition::parse(expression.clone())
+                .map(|_| ())
+                .map_err(|e| format!("!expr: {e}")),
+            _ => Err("!expr must be a string".into()),
+        },
+        Value::Tagged(v) if v.tag == "secret" => match &v.value {
+            Value::String(_) => Ok(()),
+            _ => Err("!secret must be a string".into()),
+        },
+        Value::Tagged(v) => validate_tagged_values(&v.value),
+        Value::Sequence(v) => v.iter().try_for_each(validate_tagged_values),
+        Value::Mapping(v) => v.values().try_for_each(validate_tagged_values),
+        _ => Ok(()),
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -29,6 +149,10 @@ pub struct ResolvedGroup {
         deserialize_with = "deserialize_map_to_map_of_named::<RawDeclaration, _, _>"
     )]
     pub declarations: HashMap<String, Declaration>,
+    // NOTE: the files each !expr was declared in, since states lose their
+    // origin once merged (for error messages)
+    #[serde(skip)]
+    pub expression_paths: HashMap<String, Vec<PathBuf>>,
 }
 
 impl ResolvedGroup {
@@ -36,6 +160,47 @@ impl ResolvedGroup {
         Self {
             vars,
             declarations: HashMap::new(),
+            expression_paths: HashMap::new(),
+        }
+    }
+
+    pub fn record_expression_paths(&mut self, group: &Group) {
+        let values = group
+            .vars
+            .values()
+            .chain(group.declarations.values().flat_map(|d| d.states.iter()));
+
+        let mut expressions = vec![];
+        for value in values {
+            collect_expressions(value, &mut expressions);
+        }
+
+        for expression in expressions {
+            let paths = self
+                .expression_paths
+                .entry(expression.to_string())
+                .or_default();
+            if !paths.contains(&group.path) {
+                paths.push(group.path.clone());
+            }
+        }
+    }
+}
+
+fn collect_expressions<'a>(value: &'a Value, expressions: &mut Vec<&'a str>) {
+    match value {
+        Value::Tagged(v) if v.tag == "expr" => {
+            if let Value::String(expression) = &v.value {
+                expressions.push(expression);
+            }
+        }
+        Value::Tagged(v) => collect_expressions(&v.value, expressions),
+        Value::Sequence(v) => {
+            v.iter().for_each(|v| collect_expressions(v, expressions));
+        }
+        Value::Mapping(v) => {
+            v.values().for_each(|v| collect_expressions(v, expressions));
         }
+        _ => (),
     }
 }
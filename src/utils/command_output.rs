@@ -0,0 +1,103 @@
+use lazy_static::lazy_static;
+use std::{collections::HashMap, process::Command, sync::Mutex};
+
+// NOTE: commands (by program and args) are only run once per run, since vars
+// are rendered in multiple passes and the same command may be used by
+// multiple groups
+lazy_static! {
+    static ref COMMAND_OUTPUT_CACHE: Mutex<HashMap<Vec<String>, String>> =
+        Mutex::new(HashMap::new());
+}
+
+// NOTE: runs the program directly (not through a shell), returning its
+// (trimmed) stdout, or an error including its stderr if it fails
+pub fn command_output(
+    program: &str,
+    args: &[&str],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let key = std::iter::once(program)
+        .chain(args.iter().copied())
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+
+    let cached = COMMAND_OUTPUT_CACHE
+        .lock()
+        .expect("command output cache lock")
+        .get(&key)
+        .cloned();
+    if let Some(output) = cached {
+        return Ok(output);
+    }
+
+    let command = key.join(" ");
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("{e}: {command}"))?;
+    if !output.status.success() {
+        let mut message =
+            format!("command failed with {}: {command}", output.status);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stderr.trim().is_empty() {
+            message.push_str(&format!("\n{}", stderr.trim_end()));
+        }
+        return Err(message.into());
+    }
+
+    let output = String::from_utf8(output.stdout)
+        .map_err(|e| format!("{e}: in output of: {command}"))?
+        .trim_end()
+        .to_string();
+    COMMAND_OUTPUT_CACHE
+        .lock()
+        .expect("command output cache lock")
+        .insert(key, output.clone());
+
+    Ok(output)
+}
+
+// NOTE: runs the command through the platform's shell (ie. for pipes)
+pub fn shell_command_output(
+    command: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if cfg!(windows) {
+        command_output("cmd", &["/C", command])
+    } else {
+        command_output("sh", &["-c", command])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_output_is_trimmed() {
+        assert_eq!(command_output("echo", &["a", "b"]).unwrap(), "a b");
+        assert_eq!(shell_command_output("echo a | tr a b").unwrap(), "b");
+    }
+
+    #[test]
+    fn command_output_errors_include_stderr() {
+        let error = shell_command_output("echo nk-test-stderr >&2; exit 3")
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "command failed with exit status: 3: sh -c echo nk-test-stderr >&2; exit 3\nnk-test-stderr"
+        );
+    }
+
+    #[test]
+    fn command_output_is_cached() {
+        let path = std::env::temp_dir().join("nk-test-command-output-count");
+        std::fs::write(&path, "").unwrap();
+        let command = format!("echo x >> \"{}\"", path.display());
+
+        shell_command_output(&command).unwrap();
+        shell_command_output(&command).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "x\n");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,3 +1,5 @@
+mod command_output;
 mod deserialize_map_to_named;
 
+pub use self::command_output::*;
 pub use self::deserialize_map_to_named::*;
@@ -0,0 +1,98 @@
+use super::{get_system_facts, get_system_vars, SystemFacts, SystemVars};
+use home::home_dir;
+use serde::Serialize;
+use serde_yml::{Mapping, Value};
+
+#[derive(Serialize, Debug)]
+pub struct BuiltinVars {
+    // system vars
+    pub distro: String,
+    pub distro_version: String,
+    pub os: String,
+    pub family: String,
+    pub arch: String,
+    pub os_version: String,
+    pub kernel: String,
+    pub cpus: usize,
+    pub memory_mb: u64,
+    pub container: bool,
+    pub wsl: bool,
+
+    // user vars
+    pub hostname: String,
+    pub machine: String,
+    pub roles: Vec<String>,
+    pub user: String,
+    pub home: String,
+    pub shell: String,
+
+    // session vars
+    pub interactive: bool,
+    pub ci: bool,
+}
+
+impl BuiltinVars {
+    pub fn to_mapping(&self) -> Mapping {
+        let Value::Mapping(res) = serde_yml::to_value(self)
+            .expect("BuiltinVars.serialize should not return errors")
+        else {
+            unreachable!("BuiltinVars should always serialize to a mapping");
+        };
+
+        res
+    }
+}
+
+// TODO: consider including sources as a var (could then change ProvisionInfo to just be vars...)
+pub fn get_builtin_vars() -> Result<BuiltinVars, Box<dyn std::error::Error>> {
+    let SystemVars {
+        distro,
+        distro_version,
+        os,
+        family,
+        arch,
+    } = get_system_vars()?;
+    let hostname = hostname::get()?.to_string_lossy().to_string();
+    let user = whoami::username();
+    let SystemFacts {
+        os_version,
+        kernel,
+        cpus,
+        memory_mb,
+        container,
+        wsl,
+        shell,
+        interactive,
+        ci,
+    } = get_system_facts(&user);
+
+    Ok(BuiltinVars {
+        distro,
+        distro_version,
+        os,
+        family,
+        arch,
+        os_version,
+        kernel,
+        cpus,
+        memory_mb,
+        container,
+        wsl,
+        hostname: hostname.clone(),
+        machine: hostname, // defaults to hostname, override with: nk var set machine 'some-machine'
+        roles: vec![], // defaults to empty list, override with: nk var set roles '[some, roles]'
+        user,
+        home: get_home_dir()?,
+        shell,
+        interactive,
+        ci,
+    })
+}
+
+fn get_home_dir() -> Result<String, String> {
+    Ok(home_dir()
+        .ok_or("Could not determine home directory")?
+        .as_os_str()
+        .to_string_lossy()
+        .into())
+}
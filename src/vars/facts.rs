@@ -0,0 +1,245 @@
+use super::get_distro_version;
+use crate::utils::command_output;
+use std::{
+    env,
+    fs::read_to_string,
+    io::{stdin, stdout, IsTerminal},
+    path::Path,
+};
+
+pub struct SystemFacts {
+    pub os_version: String,
+    pub kernel: String,
+    pub cpus: usize,
+    pub memory_mb: u64,
+    pub container: bool,
+    pub wsl: bool,
+    pub shell: String,
+    pub interactive: bool,
+    pub ci: bool,
+}
+
+// NOTE: facts are best effort, anything that can't be determined falls back to
+// an empty/zero/false value (instead of failing)
+pub fn get_system_facts(user: &str) -> SystemFacts {
+    SystemFacts {
+        // NOTE: the same as distro_version (ie. 24.04 on ubuntu, 15.1 on macos)
+        os_version: get_distro_version(),
+        kernel: get_kernel(),
+        cpus: std::thread::available_parallelism().map_or(0, usize::from),
+        memory_mb: get_memory_mb(),
+        container: is_container(),
+        wsl: is_wsl(),
+        shell: get_default_shell(user),
+        interactive: stdin().is_terminal() && stdout().is_terminal(),
+        ci: env::var("CI")
+            .is_ok_and(|v| !matches!(v.as_str(), "" | "0" | "false")),
+    }
+}
+
+// NOTE: /etc/os-release is a list of KEY=value lines, with optionally quoted values
+pub fn parse_os_release(contents: &str, key: &str) -> Option<String> {
+    contents.lines().find_map(|line| {
+        let (k, v) = line.trim().split_once('=')?;
+        (k == key)
+            .then(|| v.trim_matches(|c| c == '"' || c == '\'').to_string())
+    })
+}
+
+fn get_kernel() -> String {
+    if cfg!(target_os = "linux") {
+        if let Ok(contents) = read_to_string("/proc/sys/kernel/osrelease") {
+            return contents.trim().to_string();
+        }
+    }
+
+    if cfg!(unix) {
+        command_output("uname", &["-r"]).unwrap_or_default()
+    } else {
+        String::new()
+    }
+}
+
+fn get_memory_mb() -> u64 {
+    if cfg!(target_os = "linux") {
+        read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|contents| parse_meminfo_total_kb(&contents))
+            .map_or(0, |kb| kb / 1024)
+    } else if cfg!(target_os = "macos") {
+        command_output("sysctl", &["-n", "hw.memsize"])
+            .ok()
+            .and_then(|bytes| bytes.parse::<u64>().ok())
+            .map_or(0, |bytes| bytes / 1024 / 1024)
+    } else {
+        0
+    }
+}
+
+// NOTE: ie. `MemTotal:       16318480 kB`
+fn parse_meminfo_total_kb(contents: &str) -> Option<u64> {
+    contents.lines().find_map(|line| {
+        line.strip_prefix("MemTotal:")?
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()
+    })
+}
+
+fn is_container() -> bool {
+    if !cfg!(target_os = "linux") {
+        return false;
+    }
+
+    env::var_os("container").is_some()
+        || Path::new("/.dockerenv").exists()
+        || Path::new("/run/.containerenv").exists()
+        || read_to_string("/proc/1/cgroup")
+            .is_ok_and(|contents| is_container_cgroup(&contents))
+}
+
+fn is_container_cgroup(contents: &str) -> bool {
+    ["docker", "kubepods", "containerd", "lxc", "libpod"]
+        .iter()
+        .any(|runtime| contents.contains(runtime))
+}
+
+fn is_wsl() -> bool {
+    if !cfg!(target_os = "linux") {
+        return false;
+    }
+
+    env::var_os("WSL_DISTRO_NAME").is_some()
+        || read_to_string("/proc/sys/kernel/osrelease")
+            .is_ok_and(|release| release.to_lowercase().contains("microsoft"))
+}
+
+// NOTE: the user's login shell (name only, ie. zsh), rather than the current one
+fn get_default_shell(user: &str) -> String {
+    let path = if cfg!(target_os = "linux") {
+        read_to_string("/etc/passwd")
+            .ok()
+            .and_then(|contents| parse_passwd_shell(&contents, user))
+    } else if cfg!(target_os = "macos") {
+        command_output(
+            "dscl",
+            &[".", "-read", &format!("/Users/{user}"), "UserShell"],
+        )
+        .ok()
+        .and_then(|output| {
+            output
+                .strip_prefix("UserShell:")
+                .map(|shell| shell.trim().to_string())
+        })
+    } else {
+        None
+    }
+    .or_else(|| env::var("SHELL").ok())
+    .unwrap_or_default();
+
+    Path::new(&path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+// NOTE: ie. `user:x:1000:1000::/home/user:/bin/zsh`
+fn parse_passwd_shell(contents: &str, user: &str) -> Option<String> {
+    contents.lines().find_map(|line| {
+        let fields = line.split(':').collect::<Vec<_>>();
+        match fields[..] {
+            [name, _, _, _, _, _, shell]
+                if name == user && !shell.is_empty() =>
+            {
+                Some(shell.to_string())
+            }
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_os_release_quoted_values() {
+        let contents = include_str!("fixtures/os-release-ubuntu");
+
+        assert_eq!(
+            parse_os_release(contents, "VERSION_ID").as_deref(),
+            Some("24.04")
+        );
+        assert_eq!(
+            parse_os_release(contents, "NAME").as_deref(),
+            Some("Ubuntu")
+        );
+    }
+
+    #[test]
+    fn parse_os_release_unquoted_values() {
+        let contents = include_str!("fixtures/os-release-alpine");
+
+        assert_eq!(
+            parse_os_release(contents, "VERSION_ID").as_deref(),
+            Some("3.20.3")
+        );
+        assert_eq!(parse_os_release(contents, "ID").as_deref(), Some("alpine"));
+    }
+
+    #[test]
+    fn parse_os_release_missing_key() {
+        // NOTE: rolling releases have no VERSION_ID
+        let contents = include_str!("fixtures/os-release-arch");
+
+        assert_eq!(parse_os_release(contents, "VERSION_ID"), None);
+        // NOTE: keys are matched exactly (not by prefix)
+        assert_eq!(parse_os_release(contents, "BUILD"), None);
+    }
+
+    #[test]
+    fn parse_meminfo() {
+        let contents = include_str!("fixtures/meminfo");
+
+        assert_eq!(parse_meminfo_total_kb(contents), Some(16_318_480));
+        assert_eq!(parse_meminfo_total_kb("MemFree: 1 kB"), None);
+    }
+
+    #[test]
+    fn parse_passwd() {
+        let contents = include_str!("fixtures/passwd");
+
+        assert_eq!(
+            parse_passwd_shell(contents, "alice").as_deref(),
+            Some("/usr/bin/zsh")
+        );
+        assert_eq!(
+            parse_passwd_shell(contents, "root").as_deref(),
+            Some("/bin/bash")
+        );
+    }
+
+    #[test]
+    fn parse_passwd_empty_shell() {
+        let contents = include_str!("fixtures/passwd");
+
+        assert_eq!(parse_passwd_shell(contents, "nobody"), None);
+    }
+
+    #[test]
+    fn parse_passwd_no_matching_user() {
+        let contents = include_str!("fixtures/passwd");
+
+        // NOTE: users are matched exactly (not by prefix)
+        assert_eq!(parse_passwd_shell(contents, "ali"), None);
+        assert_eq!(parse_passwd_shell(contents, "bob"), None);
+    }
+
+    #[test]
+    fn container_cgroups() {
+        assert!(is_container_cgroup(include_str!("fixtures/cgroup-docker")));
+        assert!(is_container_cgroup(include_str!("fixtures/cgroup-podman")));
+        assert!(!is_container_cgroup(include_str!("fixtures/cgroup-host")));
+    }
+}
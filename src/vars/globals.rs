@@ -0,0 +1,180 @@
+use super::{
+    get_builtin_vars, get_env_vars, select_machine_profile, MachineProfile,
+    VarSources,
+};
+use crate::{
+    lock::{write_atomically, NkLock},
+    merge::merge_vars,
+};
+use serde::{Deserialize, Serialize};
+use serde_yml::{Mapping, Value};
+use std::{collections::HashMap, path::PathBuf, str::FromStr};
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Globals {
+    pub vars: Mapping,
+    #[serde(flatten)]
+    pub sources: VarSources,
+}
+
+impl Globals {
+    pub fn path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+        Ok(PathBuf::from_str(&shellexpand::tilde("~/.nk/globals.yml"))?)
+    }
+
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = Self::path()?;
+
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(val) => Ok(Some(val)),
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::NotFound => Ok(None),
+                _ => Err(format!("{}: {}", e, path.display())),
+            },
+        }?;
+
+        contents.map_or_else(
+            || Ok(Self::default()),
+            |contents| {
+                Self::parse(&contents)
+                    .map_err(|e| format!("{e}: {}", path.display()).into())
+            },
+        )
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        // NOTE: an empty file is equivalent to no globals
+        if contents.trim().is_empty() {
+            return Ok(Self::default());
+        }
+
+        Ok(serde_yml::from_str(contents)?)
+    }
+
+    pub fn save(
+        &self,
+        lock: &NkLock,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // NOTE: key order is preserved by the mapping, but comments are lost
+        // when serializing, so at least the leading comments are kept
+        let header = std::fs::read_to_string(Self::path()?)
+            .map(|contents| leading_comments(&contents))
+            .unwrap_or_default();
+
+        Self::write(&format!("{header}{}", serde_yml::to_string(&self)?), lock)
+    }
+
+    // NOTE: writes the contents as is (ie. to preserve comments when editing)
+    pub fn write(
+        contents: &str,
+        lock: &NkLock,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let path = Self::path()?;
+
+        write_atomically(&path, contents, lock).map_err(|e| {
+            format!("{e}: nk globals should be writable...").into()
+        })
+    }
+
+    // NOTE: in order of precedence (lowest first): globals, global var
+    // sources, env vars
+    pub fn overrides(
+        &self,
+    ) -> Result<[Mapping; 3], Box<dyn std::error::Error>> {
+        Ok([self.vars.clone(), self.sources.resolve()?, get_env_vars()])
+    }
+}
+
+fn leading_comments(contents: &str) -> String {
+    contents
+        .lines()
+        .take_while(|l| l.trim().is_empty() || l.trim_start().starts_with('#'))
+        .fold(String::new(), |mut output, l| {
+            output.push_str(l);
+            output.push('\n');
+            output
+        })
+}
+
+// NOTE: in order of precedence: env vars, global var sources, globals, machine
+// profile, builtin (each merged over the previous, ie. `!append` to roles)
+pub fn get_global_vars(
+    sources: &[PathBuf],
+) -> Result<Mapping, Box<dyn std::error::Error>> {
+    let overrides = Globals::load()?.overrides()?;
+    let mut vars = get_machine_vars(sources, &overrides)?;
+
+    for o in overrides {
+        merge_vars(&mut vars, o);
+    }
+
+    Ok(vars)
+}
+
+// NOTE: builtin vars with the machine profile (if any, from the sources)
+// merged over them, ie. the vars that globals are merged into
+// NOTE: the profile is selected with the overrides applied (ie. `machine`)
+pub fn get_machine_vars(
+    sources: &[PathBuf],
+    overrides: &[Mapping],
+) -> Result<Mapping, Box<dyn std::error::Error>> {
+    let mut vars = get_builtin_vars()?.to_mapping();
+
+    let profiles = MachineProfile::find_all(sources)?;
+    let mut selection_vars = vars.clone();
+    for o in overrides {
+        selection_vars.extend(o.clone());
+    }
+    if let Some(profile) = select_machine_profile(&profiles, &selection_vars)? {
+        merge_vars(&mut vars, profile.to_vars());
+    }
+
+    Ok(vars)
+}
+
+// NOTE: where each global var was (last) set, for error messages
+pub fn get_global_var_origins(
+    sources: &[PathBuf],
+) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+    let mut origins = HashMap::new();
+    insert_origins(
+        &mut origins,
+        get_builtin_vars()?.to_mapping().keys(),
+        "builtin",
+    );
+
+    let vars = get_global_vars(sources)?;
+    let profiles = MachineProfile::find_all(sources)?;
+    if let Some(profile) = select_machine_profile(&profiles, &vars)? {
+        let path = profile.path.display().to_string();
+        insert_origins(&mut origins, profile.to_vars().keys(), &path);
+    }
+
+    let globals = Globals::load()?;
+    let path = Globals::path()?.display().to_string();
+    insert_origins(&mut origins, globals.vars.keys(), &path);
+    for name in globals
+        .sources
+        .from_env
+        .keys()
+        .chain(globals.sources.from_command.keys())
+    {
+        origins.insert(name.clone(), path.clone());
+    }
+
+    for name in get_env_vars().keys().filter_map(Value::as_str) {
+        origins.insert(name.to_string(), format!("env NK_VAR_{name}"));
+    }
+
+    Ok(origins)
+}
+
+fn insert_origins<'a>(
+    origins: &mut HashMap<String, String>,
+    names: impl Iterator<Item = &'a Value>,
+    origin: &str,
+) {
+    for name in names.filter_map(Value::as_str) {
+        origins.insert(name.to_string(), origin.to_string());
+    }
+}
@@ -0,0 +1,168 @@
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, OneOrMany};
+use serde_yml::{Mapping, Value};
+use std::{ffi::OsStr, path::PathBuf};
+
+// NOTE: machines/<name>.yml in any source, selected by `machine` (when set,
+// ie. with `nk var set machine`) or otherwise by hostname
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MachineProfile {
+    #[serde(skip)]
+    pub name: String,
+    #[serde(skip)]
+    pub path: PathBuf,
+
+    // NOTE: glob patterns (ie. `build-*`), the name itself always matches
+    #[serde_as(deserialize_as = "OneOrMany<_>")]
+    #[serde(default)]
+    pub hostnames: Vec<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub vars: Mapping,
+}
+
+impl MachineProfile {
+    pub fn find_all(
+        sources: &[PathBuf],
+    ) -> Result<Vec<Self>, Box<dyn std::error::Error>> {
+        let mut profiles: Vec<Self> = vec![];
+
+        for source in sources {
+            let directory = source.join("machines");
+            if !directory.is_dir() {
+                continue;
+            }
+
+            let mut paths = std::fs::read_dir(&directory)
+                .map_err(|e| format!("{}: {}", e, directory.display()))?
+                .map(|res| res.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            paths.sort();
+
+            for path in paths {
+                if !path.is_file()
+                    || !matches!(
+                        path.extension().and_then(OsStr::to_str),
+                        Some("yml" | "yaml")
+                    )
+                {
+                    continue;
+                }
+
+                let profile = Self::from_path(path)?;
+                if let Some(existing) =
+                    profiles.iter().find(|p| p.name == profile.name)
+                {
+                    return Err(format!(
+                        "duplicate machine profile: {}: {} and {}",
+                        profile.name,
+                        existing.path.display(),
+                        profile.path.display()
+                    )
+                    .into());
+                }
+                profiles.push(profile);
+            }
+        }
+
+        Ok(profiles)
+    }
+
+    fn from_path(path: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("{}: {}", e, path.display()))?;
+
+        // NOTE: an empty profile is valid (ie. just to name the machine)
+        let profile: Option<Self> = serde_yml::from_str(&contents)
+            .map_err(|e| format!("{}: {}", e, path.display()))?;
+        let mut profile = profile.unwrap_or_else(|| Self {
+            name: String::new(),
+            path: PathBuf::new(),
+            hostnames: vec![],
+            roles: vec![],
+            vars: Mapping::new(),
+        });
+
+        for pattern in &profile.hostnames {
+            Pattern::new(pattern).map_err(|e| {
+                format!(
+                    "{e}: in hostname pattern: {pattern}: {}",
+                    path.display()
+                )
+            })?;
+        }
+
+        profile.name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        profile.path = path;
+
+        Ok(profile)
+    }
+
+    fn matches_hostname(&self, hostname: &str) -> bool {
+        self.name == hostname
+            || self.hostnames.iter().any(|pattern| {
+                Pattern::new(pattern).is_ok_and(|p| p.matches(hostname))
+            })
+    }
+
+    // NOTE: profile vars are merged over builtin vars (but under globals), with
+    // roles/machine taking precedence over the same keys in vars
+    pub fn to_vars(&self) -> Mapping {
+        let mut vars = self.vars.clone();
+        if !self.roles.is_empty() {
+            vars.insert(
+                "roles".into(),
+                self.roles.iter().map(|r| Value::from(r.as_str())).collect(),
+            );
+        }
+        vars.insert("machine".into(), self.name.clone().into());
+
+        vars
+    }
+}
+
+// NOTE: `machine` defaults to the hostname, so it's only considered to be set
+// explicitly when they differ
+pub fn select_machine_profile<'a>(
+    profiles: &'a [MachineProfile],
+    vars: &Mapping,
+) -> Result<Option<&'a MachineProfile>, Box<dyn std::error::Error>> {
+    let hostname = vars.get("hostname").and_then(Value::as_str);
+    let machine = vars.get("machine").and_then(Value::as_str);
+
+    if let Some(machine) = machine.filter(|m| Some(*m) != hostname) {
+        return Ok(profiles.iter().find(|p| p.name == machine));
+    }
+
+    let Some(hostname) = hostname else {
+        return Ok(None);
+    };
+
+    let matching = profiles
+        .iter()
+        .filter(|p| p.matches_hostname(hostname))
+        .collect::<Vec<_>>();
+
+    match matching[..] {
+        [] => Ok(None),
+        [profile] => Ok(Some(profile)),
+        _ => matching
+            .iter()
+            .find(|p| p.name == hostname)
+            .map(|p| Some(*p))
+            .ok_or_else(|| {
+                format!(
+                    "multiple machine profiles match hostname: {hostname}: {} (select one with: nk var set machine <name>)",
+                    matching.iter().map(|p| p.name.as_str()).collect::<Vec<_>>().join(", ")
+                )
+                .into()
+            }),
+    }
+}
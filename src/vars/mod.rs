@@ -0,0 +1,13 @@
+mod builtin;
+mod facts;
+mod globals;
+mod machine;
+mod sources;
+mod system;
+
+pub use self::builtin::*;
+pub use self::facts::*;
+pub use self::globals::*;
+pub use self::machine::*;
+pub use self::sources::*;
+pub use self::system::*;
@@ -0,0 +1,57 @@
+use crate::utils::shell_command_output;
+use serde::{Deserialize, Serialize};
+use serde_yml::{Mapping, Value};
+use std::{collections::BTreeMap, env};
+
+// NOTE: vars sourced from the environment or the output of a command, declared
+// in globals.yml or state groups. They're only evaluated when used (ie. when the
+// group declaring them matches), and commands are only run once per run
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct VarSources {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub from_env: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub from_command: BTreeMap<String, String>,
+}
+
+impl VarSources {
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.from_env.contains_key(name) || self.from_command.contains_key(name)
+    }
+
+    pub fn resolve(&self) -> Result<Mapping, Box<dyn std::error::Error>> {
+        let mut vars = Mapping::new();
+
+        // NOTE: unset env vars are skipped (so earlier values still apply)
+        for (name, env_name) in &self.from_env {
+            if let Ok(value) = env::var(env_name) {
+                vars.insert(name.as_str().into(), parse_env_value(&value));
+            }
+        }
+
+        for (name, command) in &self.from_command {
+            let output = shell_command_output(command)
+                .map_err(|e| format!("{e}: in from_command for var: {name}"))?;
+            vars.insert(name.as_str().into(), output.into());
+        }
+
+        Ok(vars)
+    }
+}
+
+// NOTE: values are parsed as yaml (ie. NK_VAR_roles='[some, roles]'), falling
+// back to the raw string
+fn parse_env_value(value: &str) -> Value {
+    serde_yml::from_str(value).unwrap_or_else(|_| value.into())
+}
+
+// NOTE: vars set via NK_VAR_{name} environment variables
+pub fn get_env_vars() -> Mapping {
+    env::vars()
+        .filter_map(|(k, v)| {
+            k.strip_prefix("NK_VAR_")
+                .filter(|name| !name.is_empty())
+                .map(|name| (name.into(), parse_env_value(&v)))
+        })
+        .collect()
+}
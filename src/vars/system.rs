@@ -0,0 +1,221 @@
+use super::parse_os_release;
+use lazy_static::lazy_static;
+use os_info::{Info, Type};
+use std::env;
+use std::process::Command;
+use strum::{Display, EnumIter, EnumString};
+
+lazy_static! {
+    // NOTE: detected once, since distro, distro_version and os_version are all
+    // derived from them (and detection may run commands, ie. sw_vers)
+    static ref OS_INFO: Info = os_info::get();
+    static ref DISTRO_VERSION: String = read_distro_version();
+}
+
+pub struct SystemVars {
+    pub distro: String,
+    // NOTE: empty if unknown
+    pub distro_version: String,
+    pub os: String,
+    pub family: String,
+    pub arch: String,
+}
+
+pub fn get_system_vars() -> Result<SystemVars, Box<dyn std::error::Error>> {
+    Ok(SystemVars {
+        distro: get_system_distro().to_string(),
+        distro_version: get_distro_version(),
+        os: get_system_os()?.to_string(),
+        family: get_system_family()?.to_string(),
+        arch: get_system_arch()?.to_string(),
+    })
+}
+
+// TODO: re-order so it's more reasonable to append new values
+#[derive(Clone, Copy, EnumIter, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum SystemDistro {
+    Alpine,
+    Amazon,
+    Android,
+    Arch,
+    Centos,
+    Debian,
+    Dragonfly,
+    Emscripten,
+    Endeavouros,
+    Fedora,
+    Freebsd,
+    Garuda,
+    Gentoo,
+    Hardenedbsd,
+    Illumos,
+    Linux,
+    Manjaro,
+    Mariner,
+    Midnightbsd,
+    Mint,
+    Netbsd,
+    Nixos,
+    Openbsd,
+    Opensuse,
+    Oracle,
+    Pop,
+    Raspbian,
+    Redhat,
+    RedhatEnterprise,
+    Redox,
+    Solus,
+    Suse,
+    Ubuntu,
+    Windows,
+    // macos
+    Sequoia,
+    Sonoma,
+    Ventura,
+    Monterey,
+    BigSur,
+    Catalina,
+    Mojave,
+    HighSierra,
+    Sierra,
+    // unknown
+    Unknown,
+}
+
+fn get_system_distro() -> SystemDistro {
+    match OS_INFO.os_type() {
+        Type::Alpine => SystemDistro::Alpine,
+        Type::Amazon => SystemDistro::Amazon,
+        Type::Android => SystemDistro::Android,
+        Type::Arch => SystemDistro::Arch,
+        Type::CentOS => SystemDistro::Centos,
+        Type::Debian => SystemDistro::Debian,
+        Type::DragonFly => SystemDistro::Dragonfly,
+        Type::Emscripten => SystemDistro::Emscripten,
+        Type::EndeavourOS => SystemDistro::Endeavouros,
+        Type::Fedora => SystemDistro::Fedora,
+        Type::FreeBSD => SystemDistro::Freebsd,
+        Type::Garuda => SystemDistro::Garuda,
+        Type::Gentoo => SystemDistro::Gentoo,
+        Type::HardenedBSD => SystemDistro::Hardenedbsd,
+        Type::Illumos => SystemDistro::Illumos,
+        Type::Linux => SystemDistro::Linux,
+        Type::Macos => get_macos_distro(&get_distro_version()),
+        Type::Manjaro => SystemDistro::Manjaro,
+        Type::Mariner => SystemDistro::Mariner,
+        Type::MidnightBSD => SystemDistro::Midnightbsd,
+        Type::Mint => SystemDistro::Mint,
+        Type::NetBSD => SystemDistro::Netbsd,
+        Type::NixOS => SystemDistro::Nixos,
+        Type::OpenBSD => SystemDistro::Openbsd,
+        Type::openSUSE => SystemDistro::Opensuse,
+        Type::OracleLinux => SystemDistro::Oracle,
+        Type::Pop => SystemDistro::Pop,
+        Type::Raspbian => SystemDistro::Raspbian,
+        Type::Redhat => SystemDistro::Redhat,
+        Type::RedHatEnterprise => SystemDistro::RedhatEnterprise,
+        Type::Redox => SystemDistro::Redox,
+        Type::Solus => SystemDistro::Solus,
+        Type::SUSE => SystemDistro::Suse,
+        Type::Ubuntu => SystemDistro::Ubuntu,
+        Type::Windows => SystemDistro::Windows,
+        _ => SystemDistro::Unknown,
+    }
+}
+
+// NOTE: also used for the os_version fact
+pub fn get_distro_version() -> String {
+    DISTRO_VERSION.clone()
+}
+
+fn read_distro_version() -> String {
+    match OS_INFO.os_type() {
+        Type::Macos => get_macos_version(),
+        _ if cfg!(target_os = "linux") => {
+            std::fs::read_to_string("/etc/os-release")
+                .ok()
+                .and_then(|contents| parse_os_release(&contents, "VERSION_ID"))
+        }
+        _ => match OS_INFO.version() {
+            os_info::Version::Unknown => None,
+            version => Some(version.to_string()),
+        },
+    }
+    .unwrap_or_default()
+}
+
+fn get_macos_version() -> Option<String> {
+    let result = Command::new("sw_vers")
+        .args(["-productVersion"])
+        .output()
+        .ok()?;
+    let output = String::from_utf8(result.stdout).ok()?;
+
+    Some(output.trim_end().to_string())
+}
+
+// NOTE: unrecognized (ie. newer) versions are treated as an unknown distro
+fn get_macos_distro(version_string: &str) -> SystemDistro {
+    let version = version_string.split('.').collect::<Vec<_>>();
+
+    match version[..] {
+        ["15", ..] => SystemDistro::Sequoia,
+        ["14", ..] => SystemDistro::Sonoma,
+        ["13", ..] => SystemDistro::Ventura,
+        ["12", ..] => SystemDistro::Monterey,
+        ["11", ..] => SystemDistro::BigSur,
+        ["10", "15", ..] => SystemDistro::Catalina,
+        ["10", "14", ..] => SystemDistro::Mojave,
+        ["10", "13", ..] => SystemDistro::HighSierra,
+        ["10", "12", ..] => SystemDistro::Sierra,
+        _ => SystemDistro::Unknown,
+    }
+}
+
+#[derive(Clone, Copy, EnumIter, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum SystemOs {
+    Linux,
+    Macos,
+    Windows,
+}
+
+fn get_system_os() -> Result<SystemOs, Box<dyn std::error::Error>> {
+    match env::consts::OS {
+        "linux" => Ok(SystemOs::Linux),
+        "macos" => Ok(SystemOs::Macos),
+        "windows" => Ok(SystemOs::Windows),
+        os => Err(format!("unsupported os: {os}").into()),
+    }
+}
+
+#[derive(Clone, Copy, EnumIter, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum SystemFamily {
+    Unix,
+    Windows,
+}
+
+fn get_system_family() -> Result<SystemFamily, Box<dyn std::error::Error>> {
+    match env::consts::FAMILY {
+        "unix" => Ok(SystemFamily::Unix),
+        "windows" => Ok(SystemFamily::Windows),
+        os => Err(format!("unsupported os family: {os}").into()),
+    }
+}
+
+#[derive(Clone, Copy, EnumIter, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum SystemArch {
+    X86_64,
+    Aarch64,
+}
+
+fn get_system_arch() -> Result<SystemArch, Box<dyn std::error::Error>> {
+    match env::consts::ARCH {
+        "x86_64" => Ok(SystemArch::X86_64),
+        "aarch64" => Ok(SystemArch::Aarch64),
+        os => Err(format!("unsupported os arch: {os}").into()),
+    }
+}